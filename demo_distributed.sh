@@ -43,12 +43,9 @@ echo ""
 
 # 提交工作流
 echo ""
-echo "📤 步骤3: 生成并提交测试工作流 (50个并行节点)"
+echo "📤 步骤3: 提交测试工作流 (matrix 展开为 50 个并行节点)"
 echo ""
 
-# 生成测试文件
-python3 benchmarks/gen_workflow.py
-
 time ./target/release/workflow-engine submit -f benchmarks/distributed_flat.yaml -c http://localhost:8080
 
 echo ""
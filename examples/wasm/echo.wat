@@ -0,0 +1,30 @@
+;; Minimal WASI command: wraps the JSON read from stdin as {"echo": <stdin>}
+(module
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+
+  ;; 0..8: iovec, 8..12: bytes read/written, 16..: prefix and suffix, 1024..: input buffer
+  (data (i32.const 16) "{\"echo\":")
+  (data (i32.const 32) "}\n")
+
+  (func $write (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 0) (local.get $ptr))
+    (i32.store (i32.const 4) (local.get $len))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+
+  (func (export "_start")
+    (local $n i32)
+    (call $write (i32.const 16) (i32.const 8))
+    (block $done
+      (loop $copy
+        (i32.store (i32.const 0) (i32.const 1024))
+        (i32.store (i32.const 4) (i32.const 4096))
+        (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+        (local.set $n (i32.load (i32.const 8)))
+        (br_if $done (i32.eqz (local.get $n)))
+        (call $write (i32.const 1024) (local.get $n))
+        (br $copy)))
+    (call $write (i32.const 32) (i32.const 2))))
@@ -0,0 +1,445 @@
+//! YAML composition applied before a workflow is deserialized:
+//! `include:` pulls in fragments from other files, `templates:` defines
+//! reusable node definitions and nodes `extends:` them with overrides, and
+//! `runtimes:` settings are copied into the script nodes that use them.
+
+use crate::migrate;
+use crate::nodes::runtime;
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+/// Resolves includes and template inheritance for one document
+pub struct Composer {
+    /// Files currently being composed, used to detect include cycles
+    stack: Vec<PathBuf>,
+    /// Every file read while composing, including the root when known
+    sources: Vec<PathBuf>,
+    /// Schema version of the including document, for fragments without their own
+    inherited_version: Option<Value>,
+}
+
+impl Composer {
+    pub fn new(root: Option<&Path>) -> Self {
+        let root = root.and_then(|p| p.canonicalize().ok());
+        Self {
+            stack: root.iter().cloned().collect(),
+            sources: root.into_iter().collect(),
+            inherited_version: None,
+        }
+    }
+
+    /// Files that contributed to the composed document
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// Compose a parsed document whose relative paths are based at `base_dir`
+    pub fn compose(&mut self, doc: Value, base_dir: &Path) -> Result<Value> {
+        // Includes first, so nodes can extend templates from any file of the tree
+        let Gathered { mut doc, node_dirs } = self.gather(doc, base_dir)?;
+
+        let templates = match doc.get("templates") {
+            Some(Value::Mapping(t)) => t.clone(),
+            Some(_) => anyhow::bail!("'templates' must be a mapping of name to node definition"),
+            None => Mapping::new(),
+        };
+        let runtimes = match doc.get("runtimes") {
+            Some(Value::Mapping(r)) => r.clone(),
+            Some(_) => anyhow::bail!("'runtimes' must be a mapping of language to runtime settings"),
+            None => Mapping::new(),
+        };
+
+        if let Some(Value::Sequence(nodes)) = doc.get_mut("nodes") {
+            for node in nodes.iter_mut() {
+                expand_node(node, &templates)?;
+            }
+            for (node, dir) in nodes.iter_mut().zip(&node_dirs) {
+                resolve_node_paths(node, dir);
+                apply_runtimes(node, &runtimes, dir);
+            }
+        }
+
+        Ok(Value::Mapping(doc))
+    }
+
+    /// Migrate a document and merge in its includes, recursively
+    fn gather(&mut self, mut doc: Value, base_dir: &Path) -> Result<Gathered> {
+        let original_version = self.upgrade(&mut doc)?;
+        let Value::Mapping(mut doc) = doc else {
+            anyhow::bail!("Workflow document must be a mapping");
+        };
+
+        match doc.get_mut("runtimes") {
+            Some(Value::Mapping(r)) => resolve_runtime_paths(r.iter_mut().map(|(_, spec)| spec), base_dir),
+            Some(_) => anyhow::bail!("'runtimes' must be a mapping of language to runtime settings"),
+            None => {}
+        }
+        let own_nodes = match doc.get("nodes") {
+            Some(Value::Sequence(nodes)) => nodes.len(),
+            _ => 0,
+        };
+
+        let mut node_dirs = Vec::new();
+        if let Some(include) = doc.remove("include") {
+            let paths: Vec<String> = serde_yaml::from_value(match include {
+                Value::String(s) => Value::Sequence(vec![Value::String(s)]),
+                other => other,
+            })
+            .context("'include' must be a path or a list of paths")?;
+
+            // Later includes override earlier ones, the including file overrides all
+            let outer_version = std::mem::replace(&mut self.inherited_version, original_version);
+            let fragments: Result<Vec<Gathered>> = paths
+                .iter()
+                .map(|path| self.include(&base_dir.join(path)))
+                .collect();
+            self.inherited_version = outer_version;
+
+            let mut merged = Mapping::new();
+            for fragment in fragments? {
+                merge_fragment(&mut merged, fragment.doc);
+                node_dirs.extend(fragment.node_dirs);
+            }
+            merge_fragment(&mut merged, doc);
+            doc = merged;
+        }
+        node_dirs.extend(std::iter::repeat_n(base_dir.to_path_buf(), own_nodes));
+
+        Ok(Gathered { doc, node_dirs })
+    }
+
+    /// Migrate an older document to the current schema version, returning the
+    /// version it declared (or inherited) before migration
+    fn upgrade(&mut self, doc: &mut Value) -> Result<Option<Value>> {
+        if doc.get("version").is_none() {
+            if let (Some(version), Value::Mapping(map)) = (&self.inherited_version, &mut *doc) {
+                map.insert("version".into(), version.clone());
+            }
+        }
+        let original = doc.get("version").cloned();
+        let from = migrate::schema_version(doc)?;
+
+        let applied = migrate::migrate(doc)?;
+        if !applied.is_empty() {
+            let file = self
+                .stack
+                .last()
+                .map(|p| format!("`{}`", p.display()))
+                .unwrap_or_else(|| "Workflow".to_string());
+            log::warn!(
+                "{} uses schema version {}; migrated to {} in memory ({}). Run `workflow-engine migrate` to update it.",
+                file,
+                from,
+                migrate::CURRENT_VERSION,
+                applied.join("; ")
+            );
+        }
+        Ok(original)
+    }
+
+    fn include(&mut self, path: &Path) -> Result<Gathered> {
+        let canonical = path
+            .canonicalize()
+            .with_context(|| format!("Included file `{:?}` not found", path))?;
+        if self.stack.contains(&canonical) {
+            let chain: Vec<String> = self
+                .stack
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|p| p.display().to_string())
+                .collect();
+            anyhow::bail!("Include cycle detected: {}", chain.join(" -> "));
+        }
+
+        let content = std::fs::read_to_string(&canonical)
+            .with_context(|| format!("Could not read included file `{:?}`", canonical))?;
+        let doc: Value = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse included file `{:?}`", canonical))?;
+        let base_dir = canonical.parent().unwrap_or_else(|| Path::new(".")).to_path_buf();
+
+        self.stack.push(canonical.clone());
+        if !self.sources.contains(&canonical) {
+            self.sources.push(canonical);
+        }
+        let gathered = self.gather(doc, &base_dir);
+        self.stack.pop();
+        gathered
+    }
+}
+
+/// A document with its includes merged in, before templates are applied
+struct Gathered {
+    doc: Mapping,
+    /// Directory of the file each of `doc`'s nodes came from, in order
+    node_dirs: Vec<PathBuf>,
+}
+
+/// Merge a fragment into `target`: nodes and inputs are appended, mappings
+/// (global, templates, outputs, ...) are merged key by key, scalars replaced
+fn merge_fragment(target: &mut Mapping, fragment: Mapping) {
+    for (key, value) in fragment {
+        match (target.get_mut(&key), value) {
+            (Some(Value::Sequence(existing)), Value::Sequence(items))
+                if matches!(key.as_str(), Some("nodes" | "inputs")) =>
+            {
+                existing.extend(items);
+            }
+            (Some(Value::Mapping(existing)), Value::Mapping(entries)) => {
+                for (k, v) in entries {
+                    existing.insert(k, v);
+                }
+            }
+            (_, value) => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Replace a node's `extends:` with the template it names, recursing into loop steps
+fn expand_node(node: &mut Value, templates: &Mapping) -> Result<()> {
+    if node.get("extends").is_some() {
+        let mut chain = Vec::new();
+        *node = resolve_extends(node.clone(), templates, &mut chain)?;
+
+        // `with:` supplies values for `${name}` placeholders in the template
+        if let Some(Value::Mapping(args)) = node.as_mapping_mut().and_then(|m| m.remove("with")) {
+            substitute(node, &args);
+        }
+    } else if let Value::Mapping(map) = node {
+        if map.remove("with").is_some() {
+            let id = map.get("id").and_then(|id| id.as_str()).unwrap_or("?");
+            log::warn!("Node '{}' has 'with' but no 'extends'; ignoring it", id);
+        }
+    }
+
+    if let Some(Value::Sequence(steps)) = node.get_mut("params").and_then(|p| p.get_mut("steps")) {
+        for step in steps.iter_mut() {
+            expand_node(step, templates)?;
+        }
+    }
+    Ok(())
+}
+
+fn resolve_extends(node: Value, templates: &Mapping, chain: &mut Vec<String>) -> Result<Value> {
+    let Value::Mapping(mut node) = node else {
+        anyhow::bail!("Node definition must be a mapping");
+    };
+    let Some(parent) = node.remove("extends") else {
+        return Ok(Value::Mapping(node));
+    };
+    let name = parent
+        .as_str()
+        .context("'extends' must be the name of a template")?
+        .to_string();
+
+    if chain.contains(&name) {
+        chain.push(name);
+        anyhow::bail!("Template cycle detected: {}", chain.join(" -> "));
+    }
+    let template = templates
+        .get(name.as_str())
+        .with_context(|| format!("Unknown template '{}'", name))?
+        .clone();
+    chain.push(name);
+
+    let mut base = resolve_extends(template, templates, chain)?;
+    deep_merge(&mut base, Value::Mapping(node));
+    Ok(base)
+}
+
+fn deep_merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Replace `${name}` placeholders; a string that is exactly one placeholder
+/// takes the argument's value as-is so numbers and lists keep their type
+fn substitute(value: &mut Value, args: &Mapping) {
+    match value {
+        Value::String(s) => {
+            for (key, arg) in args {
+                let Some(key) = key.as_str() else { continue };
+                let placeholder = format!("${{{}}}", key);
+                if *s == placeholder {
+                    *value = arg.clone();
+                    return;
+                }
+                if s.contains(&placeholder) {
+                    let text = match arg {
+                        Value::String(a) => a.clone(),
+                        other => serde_yaml::to_string(other)
+                            .map(|t| t.trim_end().to_string())
+                            .unwrap_or_default(),
+                    };
+                    *s = s.replace(&placeholder, &text);
+                }
+            }
+        }
+        Value::Sequence(items) => items.iter_mut().for_each(|v| substitute(v, args)),
+        Value::Mapping(map) => map.iter_mut().for_each(|(_, v)| substitute(v, args)),
+        _ => {}
+    }
+}
+
+/// Make relative `venv` and `mirror` paths absolute, based at the file that declares them
+fn resolve_runtime_paths<'a>(specs: impl Iterator<Item = &'a mut Value>, base_dir: &Path) {
+    for spec in specs {
+        for key in ["venv", "mirror"] {
+            if let Some(Value::String(path)) = spec.get_mut(key) {
+                if !Path::new(path.as_str()).is_absolute() {
+                    *path = base_dir.join(&*path).to_string_lossy().to_string();
+                }
+            }
+        }
+    }
+}
+
+/// Layer the workflow's `runtimes:` entry for a script node's language under
+/// the node's own `runtime:`, so the node carries its settings to workers
+fn apply_runtimes(node: &mut Value, runtimes: &Mapping, base_dir: &Path) {
+    if node.get("type").and_then(|t| t.as_str()) == Some("script") {
+        if let Some(Value::Mapping(params)) = node.get_mut("params") {
+            resolve_runtime_paths(params.get_mut("runtime").into_iter(), base_dir);
+            let language = params.get("language").and_then(|l| l.as_str()).unwrap_or("python");
+            let entry = runtimes
+                .get(language)
+                .or_else(|| runtimes.get(runtime::canonical_language(language)));
+            if let Some(entry) = entry {
+                let mut spec = entry.clone();
+                if let Some(own) = params.remove("runtime") {
+                    deep_merge(&mut spec, own);
+                }
+                params.insert("runtime".into(), spec);
+            }
+        }
+    }
+
+    if let Some(Value::Sequence(steps)) = node.get_mut("params").and_then(|p| p.get_mut("steps")) {
+        for step in steps.iter_mut() {
+            apply_runtimes(step, runtimes, base_dir);
+        }
+    }
+}
+
+/// Make the `path` of `workflow` nodes and the `module` of script nodes absolute,
+/// so they are found relative to the file that references them, not the process cwd
+fn resolve_node_paths(node: &mut Value, base_dir: &Path) {
+    let key = match node.get("type").and_then(|t| t.as_str()) {
+        Some("workflow") => Some("path"),
+        Some("script") => Some("module"),
+        _ => None,
+    };
+    if let Some(key) = key {
+        if let Some(Value::String(path)) = node.get_mut("params").and_then(|p| p.get_mut(key)) {
+            // Templated paths are resolved at run time against the cwd
+            if !path.contains("{{") && !Path::new(path.as_str()).is_absolute() {
+                *path = base_dir.join(&*path).to_string_lossy().to_string();
+            }
+        }
+    }
+
+    if let Some(Value::Sequence(steps)) = node.get_mut("params").and_then(|p| p.get_mut("steps")) {
+        for step in steps.iter_mut() {
+            resolve_node_paths(step, base_dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workflow-engine-compose-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn compose_file(dir: &Path, name: &str) -> Result<Value> {
+        let path = dir.join(name);
+        let doc = yaml(&std::fs::read_to_string(&path).unwrap());
+        Composer::new(Some(&path)).compose(doc, dir)
+    }
+
+    #[test]
+    fn include_merges_fragments_and_the_including_file_wins() {
+        let dir = temp_dir();
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("lib/common.yaml"),
+            "global: {a: 1, b: 1}\nnodes:\n  - {id: sub, type: workflow, params: {path: child.yaml}}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main.yaml"),
+            "include: lib/common.yaml\nname: main\nglobal: {b: 2}\nnodes:\n  - {id: own, type: assign}\n",
+        )
+        .unwrap();
+
+        let doc = compose_file(&dir, "main.yaml").unwrap();
+        assert_eq!(doc["global"], yaml("{a: 1, b: 2}"));
+        assert_eq!(doc["nodes"][0]["id"], yaml("sub"));
+        assert_eq!(doc["nodes"][1]["id"], yaml("own"));
+        assert!(doc.get("include").is_none());
+        // Paths are based at the file that declares the node
+        let path = doc["nodes"][0]["params"]["path"].as_str().unwrap().to_string();
+        assert_eq!(Path::new(&path), dir.canonicalize().unwrap().join("lib/child.yaml"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn include_cycle_is_rejected() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("a.yaml"), "include: b.yaml\n").unwrap();
+        std::fs::write(dir.join("b.yaml"), "include: a.yaml\n").unwrap();
+
+        let err = compose_file(&dir, "a.yaml").unwrap_err();
+        assert!(err.to_string().contains("Include cycle detected"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extends_merges_templates_and_substitutes_with() {
+        let doc = yaml(
+            r#"
+templates:
+  base: {type: http, params: {method: GET, timeout: 10}}
+  api: {extends: base, params: {url: "https://${host}/v1", retries: "${retries}"}}
+nodes:
+  - {id: get, extends: api, with: {host: example.com, retries: 3}, params: {timeout: 30}}
+"#,
+        );
+        let doc = Composer::new(None).compose(doc, Path::new(".")).unwrap();
+        let node = &doc["nodes"][0];
+        assert_eq!(node["type"], yaml("http"));
+        assert_eq!(node["params"], yaml("{method: GET, timeout: 30, url: 'https://example.com/v1', retries: 3}"));
+        assert!(node.get("extends").is_none() && node.get("with").is_none());
+    }
+
+    #[test]
+    fn template_cycle_and_unknown_template_are_rejected() {
+        let cycle = yaml("templates: {a: {extends: b}, b: {extends: a}}\nnodes: [{id: n, extends: a}]");
+        let err = Composer::new(None).compose(cycle, Path::new(".")).unwrap_err();
+        assert!(err.to_string().contains("Template cycle detected: a -> b -> a"), "{}", err);
+
+        let unknown = yaml("nodes: [{id: n, extends: missing}]");
+        assert!(Composer::new(None).compose(unknown, Path::new(".")).is_err());
+    }
+}
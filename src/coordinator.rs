@@ -1,5 +1,6 @@
 use axum::{
-    extract::{Json, Path, State},
+    extract::{Json, Path, Query, State},
+    http::StatusCode,
     routing::{get, post},
     Router,
 };
@@ -12,19 +13,74 @@ use anyhow::{Result, Context};
 use serde_json::Value;
 use uuid::Uuid;
 
-use crate::schema::{Workflow, Node};
-use crate::memory::NodeOutput;
+use crate::{http_pool, inputs, loader, plan};
+use crate::nodes::ExecutorRegistry;
+use crate::schema::Workflow;
+use crate::engine;
+use crate::memory::{GlobalMemory, NodeMemory, NodeOutput};
+use crate::storage::{MemoryBackend, MemoryStore};
 use crate::worker::{ExecuteRequest, ExecuteResponse};
 
 #[derive(Clone)]
 struct CoordinatorState {
     inner: Arc<RwLock<CoordinatorInner>>,
+    /// Node types submissions are validated against; workers run them
+    executors: ExecutorRegistry,
 }
 
 struct CoordinatorInner {
     workers: Vec<WorkerInfo>,
     jobs: HashMap<String, JobState>,
     next_worker_index: usize,
+    backend: MemoryBackend,
+    /// Job summaries, kept in the backend so status survives restarts
+    job_index: Arc<dyn MemoryStore>,
+}
+
+impl CoordinatorInner {
+    /// Write the summary of a job to the job index
+    fn persist_job(&self, job_id: &str) {
+        if let Some(job) = self.jobs.get(job_id) {
+            let summary = serde_json::json!({
+                "name": job.workflow.name,
+                "status": job.status,
+                "completed": job.completed_nodes.len(),
+                "total": job.total_nodes,
+                "outputs": job.outputs,
+            });
+            if let Err(e) = self.job_index.set(job_id, summary) {
+                log::error!("Failed to persist job {}: {:#}", job_id, e);
+            }
+        }
+    }
+
+    /// Rebuild the status of a job that is no longer in memory (e.g. after a restart)
+    fn load_persisted_status(&self, job_id: &str, debug: bool) -> Result<Option<StatusResponse>> {
+        let Some(summary) = self.job_index.get(job_id)? else {
+            return Ok(None);
+        };
+        let status = summary["status"].as_str().unwrap_or("unknown").to_string();
+        let completed = summary["completed"].as_u64().unwrap_or(0) as usize;
+        let total = summary["total"].as_u64().unwrap_or(0) as usize;
+        let outputs = summary["outputs"].as_object().cloned();
+
+        let node_outputs = if status == "completed" {
+            let store = self.backend.open(&format!("{}/nodes", job_id))?;
+            Some(NodeMemory::with_store(store).get_all()?)
+        } else {
+            None
+        };
+
+        Ok(Some(StatusResponse {
+            job_id: job_id.to_string(),
+            progress: if total > 0 { completed as f64 / total as f64 } else { 0.0 },
+            completed,
+            total,
+            results: job_results(&status, outputs.as_ref(), node_outputs.as_ref()),
+            node_outputs: node_outputs.filter(|_| debug),
+            status,
+        }))
+    }
 }
 
 #[derive(Clone)]
@@ -37,14 +93,20 @@ struct JobState {
     workflow: Workflow,
     status: String,
     completed_nodes: HashSet<String>,
-    node_outputs: HashMap<String, NodeOutput>,
-    pending_nodes: VecDeque<String>,
+    node_outputs: NodeMemory,
+    /// Job globals, updated with what each node writes
+    global: GlobalMemory,
     total_nodes: usize,
+    /// Declared workflow outputs, resolved once the job completes
+    outputs: Option<serde_json::Map<String, Value>>,
 }
 
 #[derive(Deserialize)]
 pub struct SubmitRequest {
-    pub workflow: Workflow,
+    /// Checked and migrated like a workflow file before it is accepted
+    pub workflow: serde_yaml::Value,
+    #[serde(default)]
+    pub inputs: HashMap<String, Value>,
 }
 
 #[derive(Serialize)]
@@ -60,7 +122,17 @@ pub struct StatusResponse {
     pub progress: f64,
     pub completed: usize,
     pub total: usize,
-    pub results: Option<HashMap<String, NodeOutput>>,
+    /// Declared workflow outputs, or every node output when none are declared
+    pub results: Option<Value>,
+    /// Raw node outputs, only with `?debug=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_outputs: Option<HashMap<String, NodeOutput>>,
+}
+
+#[derive(Deserialize)]
+pub struct StatusQuery {
+    #[serde(default)]
+    pub debug: bool,
 }
 
 #[derive(Deserialize)]
@@ -75,13 +147,17 @@ pub struct RegisterWorkerResponse {
     pub worker_count: usize,
 }
 
-pub async fn run_coordinator(port: u16) -> Result<()> {
+pub async fn run_coordinator(port: u16, backend: MemoryBackend, executors: ExecutorRegistry) -> Result<()> {
+    let job_index = backend.open("jobs")?;
     let state = CoordinatorState {
         inner: Arc::new(RwLock::new(CoordinatorInner {
             workers: Vec::new(),
             jobs: HashMap::new(),
             next_worker_index: 0,
+            backend,
+            job_index,
         })),
+        executors,
     };
 
     let app = Router::new()
@@ -102,28 +178,66 @@ pub async fn run_coordinator(port: u16) -> Result<()> {
 
 async fn handle_submit(
     State(state): State<CoordinatorState>,
-    Json(req): Json<SubmitRequest>,
-) -> Json<SubmitResponse> {
+    Json(mut req): Json<SubmitRequest>,
+) -> Result<Json<SubmitResponse>, (StatusCode, String)> {
     let job_id = Uuid::new_v4().to_string();
+
+    let mut workflow = loader::parse_workflow_document(std::mem::take(&mut req.workflow))
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{:#}", e)))?;
+    let inputs = std::mem::take(&mut req.inputs);
+    inputs::apply_inputs(&mut workflow, inputs)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{:#}", e)))?;
+
+    // Expand `matrix:` nodes up front so the combinations spread across workers
+    let global = GlobalMemory::new();
+    for (key, value) in &workflow.global {
+        global.set(key.clone(), value.clone())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+    }
+    workflow.nodes = plan::expand_matrix(&workflow.nodes, &global)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{:#}", e)))?;
+    for node in &workflow.nodes {
+        state.executors.validate(node)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("{:#}", e)))?;
+    }
     
-    log::info!("📥 Received workflow submission: {}", req.workflow.name);
+    log::info!("📥 Received workflow submission: {}", workflow.name);
     log::info!("   Job ID: {}", job_id);
-    log::info!("   Total nodes: {}", req.workflow.nodes.len());
+    log::info!("   Total nodes: {}", workflow.nodes.len());
 
     // Initialize job state
-    let total_nodes = req.workflow.nodes.len();
-    let job_state = JobState {
-        workflow: req.workflow.clone(),
-        status: "pending".to_string(),
-        completed_nodes: HashSet::new(),
-        node_outputs: HashMap::new(),
-        pending_nodes: VecDeque::new(),
-        total_nodes,
-    };
+    let total_nodes = workflow.nodes.len();
 
     {
         let mut inner = state.inner.write().await;
+        let node_outputs = inner
+            .backend
+            .open(&format!("{}/nodes", job_id))
+            .map(NodeMemory::with_store)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+        let job_global = inner
+            .backend
+            .open(&format!("{}/global", job_id))
+            .map(GlobalMemory::with_store)
+            .and_then(|job_global| {
+                for (key, value) in global.get_all()? {
+                    job_global.set(key, value)?;
+                }
+                Ok(job_global)
+            })
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+
+        let job_state = JobState {
+            workflow: workflow.clone(),
+            status: "pending".to_string(),
+            completed_nodes: HashSet::new(),
+            node_outputs,
+            global: job_global,
+            total_nodes,
+            outputs: None,
+        };
         inner.jobs.insert(job_id.clone(), job_state);
+        inner.persist_job(&job_id);
     }
 
     // Start execution in background
@@ -135,10 +249,10 @@ async fn handle_submit(
         }
     });
 
-    Json(SubmitResponse {
+    Ok(Json(SubmitResponse {
         job_id,
         message: format!("Workflow submitted with {} nodes", total_nodes),
-    })
+    }))
 }
 
 async fn execute_workflow(state: CoordinatorState, job_id: String) -> Result<()> {
@@ -150,6 +264,7 @@ async fn execute_workflow(state: CoordinatorState, job_id: String) -> Result<()>
         if let Some(job) = inner.jobs.get_mut(&job_id) {
             job.status = "running".to_string();
         }
+        inner.persist_job(&job_id);
     }
 
     // Build dependency graph
@@ -240,8 +355,18 @@ async fn execute_workflow(state: CoordinatorState, job_id: String) -> Result<()>
             log::info!("✅ Workflow {} completed!", job_id);
             let mut inner = state.inner.write().await;
             if let Some(job) = inner.jobs.get_mut(&job_id) {
-                job.status = "completed".to_string();
+                match engine::resolve_outputs(&job.workflow, &job.global, &job.node_outputs) {
+                    Ok(outputs) => {
+                        job.outputs = outputs;
+                        job.status = "completed".to_string();
+                    }
+                    Err(e) => {
+                        log::error!("❌ Workflow {} outputs could not be resolved: {:#}", job_id, e);
+                        job.status = "failed".to_string();
+                    }
+                }
             }
+            inner.persist_job(&job_id);
             break;
         }
 
@@ -252,6 +377,7 @@ async fn execute_workflow(state: CoordinatorState, job_id: String) -> Result<()>
             if let Some(job) = inner.jobs.get_mut(&job_id) {
                 job.status = "failed".to_string();
             }
+            inner.persist_job(&job_id);
             break;
         }
     }
@@ -267,9 +393,9 @@ async fn execute_node_distributed(
     log::info!("   [{}] Scheduling node...", node_id);
 
     // Get node and current state
-    let (node, global_memory, node_outputs, worker) = {
+    let (node, global_memory, node_outputs, http, worker) = {
         // First scope: read data
-        let (node, global_map, node_outputs_map, worker_idx) = {
+        let (node, global_map, node_outputs_map, http, worker_idx) = {
             let inner = state.inner.read().await;
             
             if inner.workers.is_empty() {
@@ -290,10 +416,10 @@ async fn execute_node_distributed(
             let worker_idx = inner.next_worker_index % inner.workers.len();
 
             // Prepare memory
-            let global_map: HashMap<String, Value> = job.workflow.global.clone();
-            let node_outputs_map = job.node_outputs.clone();
+            let global_map: HashMap<String, Value> = job.global.get_all()?.into_iter().collect();
+            let node_outputs_map = job.node_outputs.get_all()?;
 
-            (node, global_map, node_outputs_map, worker_idx)
+            (node, global_map, node_outputs_map, job.workflow.http.clone(), worker_idx)
         };
 
         // Second scope: update worker index and get worker
@@ -303,17 +429,19 @@ async fn execute_node_distributed(
             inner.workers[worker_idx].clone()
         };
 
-        (node, global_map, node_outputs_map, worker)
+        (node, global_map, node_outputs_map, http, worker)
     };
 
     log::info!("   [{}] Executing on worker: {}", node_id, worker.id);
 
     // Send to worker
-    let client = reqwest::Client::new();
+    let client = http_pool::shared_client();
     let execute_req = ExecuteRequest {
         node: node.clone(),
         global_memory,
-        node_outputs: node_outputs,
+        node_outputs,
+        job_id: Some(job_id.clone()),
+        http,
     };
 
     let response: ExecuteResponse = client
@@ -331,7 +459,14 @@ async fn execute_node_distributed(
 
         if response.status == "success" {
             if let Some(output) = response.output {
-                job.node_outputs.insert(node_id.clone(), output);
+                let global = job.global.for_node(&node_id);
+                for (key, value) in response.global_updates {
+                    global.set(key, value)?;
+                }
+                for key in &response.global_removed {
+                    global.remove(key)?;
+                }
+                job.node_outputs.set(node_id.clone(), output)?;
                 job.completed_nodes.insert(node_id.clone());
                 log::info!("   [{}] ✓ Completed ({}/{})", node_id, job.completed_nodes.len(), job.total_nodes);
             }
@@ -346,34 +481,51 @@ async fn execute_node_distributed(
 async fn handle_status(
     State(state): State<CoordinatorState>,
     Path(job_id): Path<String>,
-) -> Json<StatusResponse> {
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<StatusResponse>, (StatusCode, String)> {
     let inner = state.inner.read().await;
+    let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e));
 
     if let Some(job) = inner.jobs.get(&job_id) {
         let progress = job.completed_nodes.len() as f64 / job.total_nodes as f64;
-        let results = if job.status == "completed" {
-            Some(job.node_outputs.clone())
-        } else {
-            None
-        };
+        let node_outputs = job.node_outputs.get_all().map_err(internal)?;
 
-        Json(StatusResponse {
+        Ok(Json(StatusResponse {
             job_id,
             status: job.status.clone(),
             progress,
             completed: job.completed_nodes.len(),
             total: job.total_nodes,
-            results,
-        })
+            results: job_results(&job.status, job.outputs.as_ref(), Some(&node_outputs)),
+            node_outputs: query.debug.then_some(node_outputs),
+        }))
+    } else if let Some(status) = inner.load_persisted_status(&job_id, query.debug).map_err(internal)? {
+        Ok(Json(status))
     } else {
-        Json(StatusResponse {
+        Ok(Json(StatusResponse {
             job_id,
             status: "not_found".to_string(),
             progress: 0.0,
             completed: 0,
             total: 0,
             results: None,
-        })
+            node_outputs: None,
+        }))
+    }
+}
+
+/// The result object of a completed job: declared outputs if any, else all node outputs
+fn job_results(
+    status: &str,
+    outputs: Option<&serde_json::Map<String, Value>>,
+    node_outputs: Option<&HashMap<String, NodeOutput>>,
+) -> Option<Value> {
+    if status != "completed" {
+        return None;
+    }
+    match outputs {
+        Some(outputs) => Some(Value::Object(outputs.clone())),
+        None => node_outputs.and_then(|n| serde_json::to_value(n).ok()),
     }
 }
 
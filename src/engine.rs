@@ -1,7 +1,15 @@
-use crate::nodes::get_executor;
-use crate::memory::{GlobalMemory, NodeMemory};
+use crate::events::RunEvent;
+use crate::http_pool::HttpPool;
+use crate::nodes::ExecutorRegistry;
+use crate::inputs;
+use crate::memory::{self, GlobalMemory, JournalEntry, NodeMemory};
+use crate::plan;
 use crate::schema::Workflow;
+use crate::storage::MemoryBackend;
+use crate::template::TemplateEngine;
 use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::Semaphore;
@@ -10,22 +18,143 @@ pub struct Engine {
     workflow: Workflow,
     global_memory: GlobalMemory,
     node_memory: NodeMemory,
+    run_id: String,
+    executors: ExecutorRegistry,
+    /// Held while the run executes so runs on one session take turns
+    session_lock: Option<Arc<tokio::sync::Mutex<()>>>,
+}
+
+/// Configures an [`Engine`]: the workflow, its inputs and where memory lives
+#[derive(Default)]
+pub struct EngineBuilder {
+    workflow: Option<Workflow>,
+    inputs: HashMap<String, Value>,
+    backend: MemoryBackend,
+    run_id: Option<String>,
+    session: Option<String>,
+    executors: Option<ExecutorRegistry>,
+}
+
+impl EngineBuilder {
+    pub fn workflow(mut self, workflow: Workflow) -> Self {
+        self.workflow = Some(workflow);
+        self
+    }
+
+    /// Values for the workflow's declared `inputs:`, validated by `build`
+    pub fn inputs(mut self, inputs: HashMap<String, Value>) -> Self {
+        self.inputs.extend(inputs);
+        self
+    }
+
+    pub fn input(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.inputs.insert(name.into(), value);
+        self
+    }
+
+    /// Where global and node memory are stored (in-memory by default)
+    pub fn memory(mut self, backend: MemoryBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Reuse a run ID to continue persisted memory; a new UUID otherwise
+    pub fn run_id(mut self, run_id: impl Into<String>) -> Self {
+        self.run_id = Some(run_id.into());
+        self
+    }
+
+    /// Session whose conversation variables persist across runs
+    pub fn session(mut self, session: impl Into<String>) -> Self {
+        self.session = Some(session.into());
+        self
+    }
+
+    /// Node types available to the run (the built-ins by default)
+    pub fn executor_registry(mut self, executors: ExecutorRegistry) -> Self {
+        self.executors = Some(executors);
+        self
+    }
+
+    pub fn build(self) -> Result<Engine> {
+        let mut workflow = self.workflow.context("EngineBuilder requires a workflow")?;
+        inputs::apply_inputs(&mut workflow, self.inputs)?;
+        inputs::check_files(&workflow)?;
+
+        let run_id = self.run_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let (mut global_memory, node_memory) = memory::open_run_memory(&self.backend, &run_id)?;
+        let session = match &self.session {
+            Some(session) => Some(memory::open_session(&self.backend, session)?),
+            None => None,
+        };
+        if let Some(session) = &session {
+            global_memory = global_memory.with_conversation(session.conversation.clone());
+        }
+
+        let mut engine = Engine::with_memory(workflow, global_memory, node_memory)?;
+        engine.run_id = run_id;
+        engine.session_lock = session.map(|session| session.lock);
+        if let Some(executors) = self.executors {
+            engine.executors = executors;
+        }
+        Ok(engine)
+    }
+}
+
+/// Everything a finished run produced
+#[derive(Debug, Clone, Serialize)]
+pub struct RunResult {
+    pub run_id: String,
+    /// Declared `outputs:`; `None` when the workflow declares none
+    pub outputs: Option<serde_json::Map<String, Value>>,
+    pub node_outputs: HashMap<String, Value>,
+    pub global_memory: HashMap<String, Value>,
+    pub conversation: HashMap<String, Value>,
+    /// Writes to global/conversation variables, in order
+    pub journal: Vec<JournalEntry>,
+}
+
+impl RunResult {
+    /// Declared outputs, or every node output when none are declared
+    pub fn results(&self) -> HashMap<String, Value> {
+        match &self.outputs {
+            Some(outputs) => outputs.clone().into_iter().collect(),
+            None => self.node_outputs.clone(),
+        }
+    }
 }
 
 impl Engine {
-    pub fn new(workflow: Workflow) -> Self {
-        let global_memory = GlobalMemory::new();
-        
-        // Initialize global memory with workflow globals
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    pub fn new(workflow: Workflow) -> Result<Self> {
+        Self::with_memory(workflow, GlobalMemory::new(), NodeMemory::new())
+    }
+
+    /// Create an engine on top of existing (possibly persistent) memories,
+    /// seeding the workflow globals that `global_memory` doesn't hold yet
+    pub fn with_memory(workflow: Workflow, global_memory: GlobalMemory, node_memory: NodeMemory) -> Result<Self> {
+        let mut global_memory = global_memory.with_http(HttpPool::new(&workflow.http));
+        // A top-level run starts the call stack with its own file
+        if let (true, Some(source)) = (global_memory.call_stack().is_empty(), &workflow.source) {
+            global_memory = global_memory.with_call_stack(vec![source.clone()]);
+        }
         for (key, value) in workflow.global.iter() {
-            global_memory.set(key.clone(), value.clone());
+            if global_memory.get(key)?.is_none() {
+                global_memory.set(key.clone(), value.clone())?;
+            }
         }
 
-        Self {
+        Ok(Self {
             workflow,
             global_memory,
-            node_memory: NodeMemory::new(),
-        }
+            node_memory,
+            run_id: uuid::Uuid::new_v4().to_string(),
+            executors: ExecutorRegistry::new(),
+            session_lock: None,
+        })
     }
 
     pub fn new_with_memory(workflow: Workflow, global_memory: GlobalMemory) -> Self {
@@ -33,18 +162,60 @@ impl Engine {
             workflow,
             global_memory,
             node_memory: NodeMemory::new(),
+            run_id: uuid::Uuid::new_v4().to_string(),
+            executors: ExecutorRegistry::new(),
+            session_lock: None,
         }
     }
 
+    /// Run nodes with `executors` instead of the built-in registry
+    pub fn with_executors(mut self, executors: ExecutorRegistry) -> Self {
+        self.executors = executors;
+        self
+    }
+
+    /// Receive this run's [`RunEvent`]s; subscribe before calling `run`
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<RunEvent> {
+        self.global_memory.events().subscribe()
+    }
+
+    /// Execute the workflow and collect its outputs and memory
+    pub async fn run(&self) -> Result<RunResult> {
+        self.execute().await?;
+        Ok(RunResult {
+            run_id: self.run_id.clone(),
+            outputs: self.resolve_outputs()?,
+            node_outputs: self.node_memory.get_all_values()?,
+            global_memory: self.global_memory.get_all()?.into_iter().collect(),
+            conversation: self.global_memory.get_all_conversation()?.into_iter().collect(),
+            journal: self.global_memory.journal(),
+        })
+    }
+
     /// Execute the workflow with automatic parallelization based on dependencies
     pub async fn execute(&self) -> Result<()> {
         log::info!("Starting workflow execution: {}", self.workflow.name);
 
+        let _session = match &self.session_lock {
+            Some(lock) => Some(lock.lock().await),
+            None => None,
+        };
+        // Seeded under the session lock so a concurrent run's writes aren't clobbered
+        seed_conversation(&self.workflow, &self.global_memory)?;
+
+        // Expand `matrix:` nodes so each combination is scheduled on its own
+        let planned = plan::expand_matrix(&self.workflow.nodes, &self.global_memory)?;
+
+        // Check params up front so a typo fails before any node has run
+        for node in &planned {
+            self.executors.validate(node)?;
+        }
+
         // Build dependency graph
         let mut dependencies: HashMap<String, HashSet<String>> = HashMap::new();
         let mut node_map = HashMap::new();
 
-        for node in &self.workflow.nodes {
+        for node in &planned {
             node_map.insert(node.id.clone(), node.clone());
             let deps = node.needs.clone().unwrap_or_default();
             dependencies.insert(node.id.clone(), deps.into_iter().collect());
@@ -73,7 +244,7 @@ impl Engine {
 
             if ready.is_empty() {
                 // Check if all nodes are done
-                if completed.len() == self.workflow.nodes.len() {
+                if completed.len() == planned.len() {
                     break; // Workflow complete
                 } else if in_progress.is_empty() {
                     // No ready nodes and nothing in progress = deadlock or missing dependency
@@ -90,8 +261,9 @@ impl Engine {
 
             for node_id in ready {
                 let node = node_map.get(&node_id).unwrap().clone();
-                let global = self.global_memory.clone();
+                let global = self.global_memory.for_node(&node_id);
                 let nodes = self.node_memory.clone();
+                let executor = self.executors.get(&node.node_type)?;
                 let permit = semaphore.clone().acquire_owned().await.unwrap();
 
                 in_progress.insert(node_id.clone());
@@ -101,19 +273,24 @@ impl Engine {
                     
                     log::info!("Executing node: {} ({})", node.name, node.id);
                     log::info!("  Input Params: {}", serde_json::to_string_pretty(&node.params).unwrap_or_default());
+                    // Steps inside loops are reported as `loop_id/step_id`
+                    let label = global.node().unwrap_or(&node.id).to_string();
+                    let events = global.events().clone();
+                    events.publish(RunEvent::NodeStarted { node: label.clone() });
                     
-                    let executor = get_executor(&node.node_type)?;
                     let result = executor.execute(&node, &global, &nodes).await;
                     
                     match result {
                         Ok(output) => {
                             log::info!("Node {} completed with status: {}", node.id, output.status);
                             log::info!("  Output: {}", serde_json::to_string_pretty(&output.output).unwrap_or_default());
-                            nodes.set(node.id.clone(), output);
+                            events.publish(RunEvent::NodeCompleted { node: label, status: output.status.clone() });
+                            nodes.set(node.id.clone(), output)?;
                             Ok(node.id)
                         }
                         Err(e) => {
                             log::error!("Node {} failed: {}", node.id, e);
+                            events.publish(RunEvent::NodeFailed { node: label, error: format!("{:#}", e) });
                             Err(e)
                         }
                     }
@@ -143,12 +320,156 @@ impl Engine {
         Ok(())
     }
 
+    /// Evaluate the declared `outputs:`; `None` when the workflow declares none
+    pub fn resolve_outputs(&self) -> Result<Option<serde_json::Map<String, serde_json::Value>>> {
+        resolve_outputs(&self.workflow, &self.global_memory, &self.node_memory)
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    pub fn workflow(&self) -> &Workflow {
+        &self.workflow
+    }
+
+    pub fn executors(&self) -> &ExecutorRegistry {
+        &self.executors
+    }
+
     pub fn get_node_memory(&self) -> &NodeMemory {
         &self.node_memory
     }
 
-    #[allow(dead_code)]
     pub fn get_global_memory(&self) -> &GlobalMemory {
         &self.global_memory
     }
 }
+
+/// Give conversation variables their declared initial value, unless the
+/// session already carries a value from an earlier run
+fn seed_conversation(workflow: &Workflow, global_memory: &GlobalMemory) -> Result<()> {
+    for (key, value) in workflow.conversation.iter() {
+        if global_memory.get_conversation(key)?.is_none() {
+            global_memory.set_conversation(key.clone(), value.clone())?;
+        }
+    }
+    Ok(())
+}
+
+/// Evaluate the declared `outputs:` of `workflow` against finished memories
+pub fn resolve_outputs(
+    workflow: &Workflow,
+    global_memory: &GlobalMemory,
+    node_memory: &NodeMemory,
+) -> Result<Option<serde_json::Map<String, serde_json::Value>>> {
+    if workflow.outputs.is_empty() {
+        return Ok(None);
+    }
+
+    let template = TemplateEngine::new(global_memory.clone(), node_memory.clone());
+    let mut outputs = serde_json::Map::new();
+    for (name, expr) in &workflow.outputs {
+        let value = template
+            .render_value(expr)
+            .with_context(|| format!("Failed to resolve output '{}'", name))?;
+        outputs.insert(name.clone(), value);
+    }
+    Ok(Some(outputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn workflow(yaml: &str) -> Workflow {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn declared_outputs_replace_memory_dump() {
+        let engine = Engine::builder()
+            .workflow(workflow(
+                r#"
+name: outputs
+version: "2.0"
+global: {items: [1, 2]}
+nodes:
+  - id: count
+    type: assign
+    params: {assignments: [{key: total, value: 3}]}
+outputs:
+  total: "{{ global.total }}"
+  items: "{{ global.items }}"
+  summary: "{{ global.total }} items"
+"#,
+            ))
+            .build()
+            .unwrap();
+        let result = engine.run().await.unwrap();
+
+        let outputs = result.outputs.clone().unwrap();
+        assert_eq!(outputs["total"], json!(3));
+        assert_eq!(outputs["items"], json!([1, 2]));
+        assert_eq!(outputs["summary"], json!("3 items"));
+        assert_eq!(result.results().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn without_outputs_results_are_the_node_outputs() {
+        let engine = Engine::new(workflow(
+            "name: plain\nversion: '2.0'\nnodes:\n  - {id: a, type: assign, params: {assignments: [{key: x, value: 1}]}}\n",
+        ))
+        .unwrap();
+        let result = engine.run().await.unwrap();
+        assert!(result.outputs.is_none());
+        assert!(result.results().contains_key("a"));
+    }
+
+    #[tokio::test]
+    async fn continued_runs_keep_persisted_globals_and_conversation() {
+        let dir = std::env::temp_dir().join(format!("workflow-engine-continue-{}", uuid::Uuid::new_v4()));
+        let yaml = r#"
+name: counter
+version: "2.0"
+global: {count: 0}
+conversation: {turns: 0}
+nodes:
+  - id: bump
+    type: assign
+    params:
+      assignments:
+        - {key: count, mode: increment}
+        - {key: turns, mode: increment, scope: conversation}
+"#;
+        for expected in 1..=2 {
+            let result = Engine::builder()
+                .workflow(workflow(yaml))
+                .memory(MemoryBackend::Json(dir.clone()))
+                .run_id("run-1")
+                .session("chat")
+                .build()
+                .unwrap()
+                .run()
+                .await
+                .unwrap();
+            assert_eq!(result.global_memory["count"], json!(expected));
+            assert_eq!(result.conversation["turns"], json!(expected));
+        }
+
+        // A new run starts from the declared globals but keeps the session
+        let result = Engine::builder()
+            .workflow(workflow(yaml))
+            .memory(MemoryBackend::Json(dir.clone()))
+            .session("chat")
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(result.global_memory["count"], json!(1));
+        assert_eq!(result.conversation["turns"], json!(3));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
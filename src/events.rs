@@ -0,0 +1,65 @@
+//! Live progress of a run: node lifecycle and process output lines.
+//!
+//! Subscribe with [`Engine::subscribe`](crate::Engine::subscribe) before
+//! calling `run`. Events are best effort: with no subscribers they are
+//! dropped, and a subscriber that falls more than [`EVENT_CAPACITY`] events
+//! behind receives `RecvError::Lagged` and skips ahead.
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber
+pub const EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RunEvent {
+    NodeStarted { node: String },
+    /// One line a shell or script node wrote, without its newline
+    NodeOutput { node: String, stream: OutputStream, line: String },
+    NodeCompleted { node: String, status: String },
+    NodeFailed { node: String, error: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+/// Fan-out of [`RunEvent`]s to any number of subscribers
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<RunEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self { sender: broadcast::channel(EVENT_CAPACITY).0 }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RunEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, event: RunEvent) {
+        // Only fails when nobody is listening
+        let _ = self.sender.send(event);
+    }
+}
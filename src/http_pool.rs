@@ -0,0 +1,244 @@
+//! Outgoing HTTP shared by every node of a run.
+//!
+//! All requests go through one process-wide `reqwest::Client`, so connections
+//! (and TLS sessions) are reused across nodes and runs. On top of it, the
+//! workflow's `http.hosts` section caps concurrency and spaces out requests
+//! per host.
+
+use anyhow::Result;
+use reqwest::{Client, RequestBuilder, Response};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// The workflow's `http:` section
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Limits by host: `api.example.com`, `*.example.com`, or `*` for every other host
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, HostLimit>,
+}
+
+impl HttpConfig {
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HostLimit {
+    /// Requests in flight at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent: Option<usize>,
+    /// Requests started per second, evenly spaced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_second: Option<f64>,
+}
+
+/// The client every executor shares unless it needs its own TLS or redirect settings
+pub fn shared_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(Client::new)
+}
+
+/// A response that keeps its host's concurrency slot until the body is consumed
+pub struct PooledResponse {
+    response: Response,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl PooledResponse {
+    pub async fn bytes(self) -> Result<Bytes> {
+        Ok(self.response.bytes().await?)
+    }
+
+    pub async fn text(self) -> Result<String> {
+        Ok(self.response.text().await?)
+    }
+
+    /// The body as it arrives; the slot is released once the stream is dropped
+    pub fn bytes_stream(self) -> impl Stream<Item = reqwest::Result<Bytes>> {
+        let permit = self.permit;
+        self.response.bytes_stream().map(move |chunk| {
+            let _ = &permit;
+            chunk
+        })
+    }
+}
+
+impl Deref for PooledResponse {
+    type Target = Response;
+
+    fn deref(&self) -> &Response {
+        &self.response
+    }
+}
+
+/// Shared client plus the per-host limits of one workflow
+#[derive(Clone, Default)]
+pub struct HttpPool {
+    limiters: Arc<Vec<(String, HostLimiter)>>,
+}
+
+struct HostLimiter {
+    slots: Option<Arc<Semaphore>>,
+    interval: Option<Duration>,
+    next_start: Arc<Mutex<Instant>>,
+}
+
+impl HttpPool {
+    pub fn new(config: &HttpConfig) -> Self {
+        let limiters = config
+            .hosts
+            .iter()
+            .map(|(pattern, limit)| {
+                let limiter = HostLimiter {
+                    slots: limit.max_concurrent.map(|n| Arc::new(Semaphore::new(n.max(1)))),
+                    interval: limit
+                        .requests_per_second
+                        .filter(|rps| *rps > 0.0)
+                        .map(|rps| Duration::from_secs_f64(1.0 / rps)),
+                    next_start: Arc::new(Mutex::new(Instant::now())),
+                };
+                (pattern.to_ascii_lowercase(), limiter)
+            })
+            .collect();
+        Self { limiters: Arc::new(limiters) }
+    }
+
+    pub fn client(&self) -> &'static Client {
+        shared_client()
+    }
+
+    /// Send a request built on any client, within its host's limits.
+    /// The concurrency slot is held until the response body has been read.
+    pub async fn send(&self, request: RequestBuilder) -> Result<PooledResponse> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let permit = match request.url().host_str() {
+            Some(host) => self.acquire(host).await,
+            None => None,
+        };
+        let response = client.execute(request).await?;
+        Ok(PooledResponse { response, permit })
+    }
+
+    /// Wait until `host` may start another request; the permit holds a concurrency slot
+    async fn acquire(&self, host: &str) -> Option<OwnedSemaphorePermit> {
+        let limiter = self.limiter(&host.to_ascii_lowercase())?;
+        let permit = match &limiter.slots {
+            Some(slots) => slots.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        if let Some(interval) = limiter.interval {
+            let start = {
+                let mut next_start = limiter.next_start.lock().await;
+                let start = (*next_start).max(Instant::now());
+                *next_start = start + interval;
+                start
+            };
+            if start > Instant::now() {
+                log::debug!("Rate limiting request to {}", host);
+            }
+            tokio::time::sleep_until(start).await;
+        }
+        permit
+    }
+
+    /// Exact host first, then the longest matching `*.suffix`, then `*`
+    fn limiter(&self, host: &str) -> Option<&HostLimiter> {
+        let find = |pattern: &str| self.limiters.iter().find(|(p, _)| p == pattern).map(|(_, l)| l);
+        find(host)
+            .or_else(|| {
+                self.limiters
+                    .iter()
+                    .filter_map(|(pattern, limiter)| {
+                        let suffix = pattern.strip_prefix("*.")?;
+                        let matches = host == suffix || host.ends_with(&format!(".{}", suffix));
+                        matches.then_some((suffix.len(), limiter))
+                    })
+                    .max_by_key(|(len, _)| *len)
+                    .map(|(_, limiter)| limiter)
+            })
+            .or_else(|| find("*"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(hosts: &[(&str, Option<usize>, Option<f64>)]) -> HttpPool {
+        let hosts = hosts
+            .iter()
+            .map(|(host, max_concurrent, requests_per_second)| {
+                let limit = HostLimit { max_concurrent: *max_concurrent, requests_per_second: *requests_per_second };
+                (host.to_string(), limit)
+            })
+            .collect();
+        HttpPool::new(&HttpConfig { hosts })
+    }
+
+    #[test]
+    fn exact_host_beats_wildcards() {
+        let pool = pool(&[("api.example.com", Some(1), None), ("*.example.com", Some(2), None), ("*", Some(3), None)]);
+        let cap = |host: &str| pool.limiter(host).and_then(|l| l.slots.as_ref()).map(|s| s.available_permits());
+        assert_eq!(cap("api.example.com"), Some(1));
+        assert_eq!(cap("cdn.example.com"), Some(2));
+        assert_eq!(cap("example.com"), Some(2));
+        assert_eq!(cap("notexample.com"), Some(3));
+        assert!(HttpPool::default().limiter("api.example.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn concurrency_is_capped_per_host() {
+        let pool = pool(&[("slow.test", Some(1), None)]);
+        let first = pool.acquire("slow.test").await;
+        assert!(first.is_some());
+        let wait = Duration::from_millis(50);
+        assert!(tokio::time::timeout(wait, pool.acquire("SLOW.test")).await.is_err());
+        assert!(tokio::time::timeout(wait, pool.acquire("other.test")).await.is_ok());
+
+        drop(first);
+        assert!(tokio::time::timeout(wait, pool.acquire("slow.test")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn requests_are_spaced_out() {
+        let pool = pool(&[("*", None, Some(20.0))]);
+        let started = Instant::now();
+        for _ in 0..3 {
+            pool.acquire("api.test").await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(95), "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn slot_is_held_until_the_body_is_read() {
+        use axum::{routing::get, Router};
+
+        let app = Router::new().route("/", get(|| async { "body" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let pool = pool(&[("127.0.0.1", Some(1), None)]);
+        let first = pool.send(pool.client().get(&url)).await.unwrap();
+        let second = pool.send(pool.client().get(&url));
+        tokio::pin!(second);
+        assert!(tokio::time::timeout(Duration::from_millis(100), &mut second).await.is_err());
+
+        assert_eq!(first.text().await.unwrap(), "body");
+        let second = tokio::time::timeout(Duration::from_secs(5), second).await.unwrap().unwrap();
+        assert_eq!(second.text().await.unwrap(), "body");
+    }
+}
@@ -0,0 +1,266 @@
+use crate::schema::{InputSpec, InputType, Workflow};
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Parse CLI `key=value` pairs, using the declared input types when present.
+///
+/// Undeclared keys keep the old behaviour: JSON if it parses, otherwise a string.
+pub fn parse_cli_inputs(workflow: &Workflow, raw: &[String]) -> Result<HashMap<String, Value>> {
+    let mut values = HashMap::new();
+    let mut errors = Vec::new();
+
+    for inp in raw {
+        let Some((key, value_str)) = inp.split_once('=') else {
+            errors.push(format!("invalid input '{}' (expected KEY=VALUE)", inp));
+            continue;
+        };
+
+        let value = match workflow.inputs.iter().find(|spec| spec.name == key) {
+            Some(spec) => match parse_typed(spec.input_type, value_str) {
+                Ok(v) => v,
+                Err(e) => {
+                    errors.push(format!("input '{}': {}", key, e));
+                    continue;
+                }
+            },
+            None => serde_json::from_str(value_str)
+                .unwrap_or_else(|_| Value::String(value_str.to_string())),
+        };
+        values.insert(key.to_string(), value);
+    }
+
+    if !errors.is_empty() {
+        anyhow::bail!("Invalid inputs:\n  - {}", errors.join("\n  - "));
+    }
+    Ok(values)
+}
+
+/// Check provided inputs against the `inputs:` declaration and fill in defaults.
+///
+/// Workflows without declared inputs accept anything, as before.
+pub fn validate_inputs(workflow: &Workflow, provided: HashMap<String, Value>) -> Result<HashMap<String, Value>> {
+    if workflow.inputs.is_empty() {
+        return Ok(provided);
+    }
+
+    let mut errors = Vec::new();
+    let mut resolved = HashMap::new();
+
+    for key in provided.keys() {
+        if !workflow.inputs.iter().any(|spec| &spec.name == key) {
+            errors.push(format!("unknown input '{}'", key));
+        }
+    }
+
+    for spec in &workflow.inputs {
+        // An input default would silently replace the declared global
+        if workflow.global.contains_key(&spec.name) {
+            errors.push(format!(
+                "input '{}' is also declared in 'global:'; set its default in 'inputs:' instead",
+                spec.name
+            ));
+            continue;
+        }
+        let value = match provided.get(&spec.name).or(spec.default.as_ref()) {
+            Some(v) => v.clone(),
+            None if spec.required => {
+                errors.push(format!("missing required input '{}' ({})", spec.name, spec.input_type));
+                continue;
+            }
+            None => continue,
+        };
+
+        if let Err(e) = check_value(spec, &value) {
+            errors.push(format!("input '{}': {}", spec.name, e));
+            continue;
+        }
+        resolved.insert(spec.name.clone(), value);
+    }
+
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "Invalid inputs for workflow '{}':\n  - {}",
+            workflow.name,
+            errors.join("\n  - ")
+        );
+    }
+    Ok(resolved)
+}
+
+/// Validate `provided` and write the result into the workflow globals
+pub fn apply_inputs(workflow: &mut Workflow, provided: HashMap<String, Value>) -> Result<()> {
+    let resolved = validate_inputs(workflow, provided)?;
+    workflow.global.extend(resolved);
+    Ok(())
+}
+
+/// Check that `file` inputs exist on this host.
+///
+/// Called where the nodes run, after [`apply_inputs`]; a coordinator skips it
+/// since its workers, not it, open the files.
+pub fn check_files(workflow: &Workflow) -> Result<()> {
+    let missing: Vec<String> = workflow
+        .inputs
+        .iter()
+        .filter(|spec| spec.input_type == InputType::File)
+        .filter_map(|spec| {
+            let path = workflow.global.get(&spec.name)?.as_str()?;
+            (!std::path::Path::new(path).is_file()).then(|| format!("input '{}': file '{}' does not exist", spec.name, path))
+        })
+        .collect();
+    if !missing.is_empty() {
+        anyhow::bail!("Invalid inputs for workflow '{}':\n  - {}", workflow.name, missing.join("\n  - "));
+    }
+    Ok(())
+}
+
+/// Human-readable usage for a workflow, in the spirit of `--help`
+pub fn describe(workflow: &Workflow, file: &str) -> String {
+    let mut out = format!("{} (version {})\n\nUsage:\n  workflow-engine run -f {}", workflow.name, workflow.version, file);
+    for spec in workflow.inputs.iter().filter(|s| s.required && s.default.is_none()) {
+        out.push_str(&format!(" -i {}=<{}>", spec.name, spec.input_type));
+    }
+    out.push_str(" [OPTIONS]\n\n");
+
+    if workflow.inputs.is_empty() {
+        out.push_str("Inputs:\n  (none declared; any -i KEY=VALUE overrides a global)\n");
+        return out;
+    }
+
+    out.push_str("Inputs:\n");
+    let width = workflow.inputs.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    for spec in &workflow.inputs {
+        let mut notes = Vec::new();
+        if spec.required {
+            notes.push("required".to_string());
+        }
+        if let Some(default) = &spec.default {
+            notes.push(format!("default: {}", default));
+        }
+        if let Some(allowed) = &spec.allowed {
+            let values: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            notes.push(format!("one of: {}", values.join(", ")));
+        }
+
+        out.push_str(&format!("  {:width$}  <{}>", spec.name, spec.input_type, width = width));
+        if !spec.description.is_empty() {
+            out.push_str(&format!("  {}", spec.description));
+        }
+        if !notes.is_empty() {
+            out.push_str(&format!(" [{}]", notes.join("; ")));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn parse_typed(input_type: InputType, raw: &str) -> Result<Value> {
+    match input_type {
+        InputType::String | InputType::File => Ok(Value::String(raw.to_string())),
+        InputType::Number => {
+            let n: serde_json::Number = serde_json::from_str(raw.trim())
+                .map_err(|_| anyhow::anyhow!("'{}' is not a number", raw))?;
+            Ok(Value::Number(n))
+        }
+        InputType::Bool => match raw.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => Ok(Value::Bool(true)),
+            "false" | "no" | "0" => Ok(Value::Bool(false)),
+            _ => anyhow::bail!("'{}' is not a boolean", raw),
+        },
+        InputType::Array | InputType::Object => serde_json::from_str(raw)
+            .map_err(|e| anyhow::anyhow!("expected JSON {}: {}", input_type, e)),
+    }
+}
+
+fn check_value(spec: &InputSpec, value: &Value) -> Result<()> {
+    let type_ok = match spec.input_type {
+        InputType::String | InputType::File => value.is_string(),
+        InputType::Number => value.is_number(),
+        InputType::Bool => value.is_boolean(),
+        InputType::Array => value.is_array(),
+        InputType::Object => value.is_object(),
+    };
+    if !type_ok {
+        anyhow::bail!("expected {}, got {}", spec.input_type, value);
+    }
+
+    if let Some(allowed) = &spec.allowed {
+        if !allowed.contains(value) {
+            let values: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            anyhow::bail!("{} is not one of {}", value, values.join(", "));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn workflow(yaml: &str) -> Workflow {
+        serde_yaml::from_str(&format!("name: test\nversion: '2.0'\nnodes: []\n{}", yaml)).unwrap()
+    }
+
+    fn provided(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    const INPUTS: &str = "
+inputs:
+  - {name: topic, type: string, required: true}
+  - {name: depth, type: number, default: 2}
+  - {name: mode, enum: [fast, deep], default: fast}
+";
+
+    #[test]
+    fn defaults_fill_in_and_values_are_written_to_globals() {
+        let mut wf = workflow(INPUTS);
+        apply_inputs(&mut wf, provided(json!({"topic": "rust", "mode": "deep"}))).unwrap();
+        assert_eq!(wf.global["topic"], json!("rust"));
+        assert_eq!(wf.global["depth"], json!(2));
+        assert_eq!(wf.global["mode"], json!("deep"));
+    }
+
+    #[test]
+    fn all_problems_are_reported_together() {
+        let mut wf = workflow(INPUTS);
+        let err = apply_inputs(&mut wf, provided(json!({"depth": "two", "mode": "slow", "extra": 1}))).unwrap_err();
+        let message = err.to_string();
+        for expected in ["unknown input 'extra'", "missing required input 'topic'", "input 'depth'", "input 'mode'"] {
+            assert!(message.contains(expected), "{} not in {}", expected, message);
+        }
+    }
+
+    #[test]
+    fn inputs_clashing_with_globals_are_rejected() {
+        let mut wf = workflow("global: {topic: fixed}\ninputs:\n  - {name: topic, default: other}\n");
+        let err = apply_inputs(&mut wf, HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("input 'topic' is also declared in 'global:'"), "{}", err);
+        assert_eq!(wf.global["topic"], json!("fixed"));
+    }
+
+    #[test]
+    fn file_inputs_must_exist_on_this_host() {
+        let mut wf = workflow("inputs:\n  - {name: doc, type: file, required: true}\n");
+        apply_inputs(&mut wf, provided(json!({"doc": "/nonexistent/input.txt"}))).unwrap();
+        let err = check_files(&wf).unwrap_err();
+        assert!(err.to_string().contains("file '/nonexistent/input.txt' does not exist"), "{}", err);
+
+        let path = std::env::current_exe().unwrap();
+        let mut wf = workflow("inputs:\n  - {name: doc, type: file, required: true}\n");
+        apply_inputs(&mut wf, provided(json!({"doc": path.to_string_lossy()}))).unwrap();
+        check_files(&wf).unwrap();
+    }
+
+    #[test]
+    fn cli_values_are_parsed_by_declared_type() {
+        let wf = workflow(INPUTS);
+        let values = parse_cli_inputs(&wf, &["depth=3".to_string(), "topic=42".to_string(), "other=[1]".to_string()]).unwrap();
+        assert_eq!(values["depth"], json!(3));
+        assert_eq!(values["topic"], json!("42"));
+        assert_eq!(values["other"], json!([1]));
+        assert!(parse_cli_inputs(&wf, &["depth=deep".to_string()]).is_err());
+    }
+}
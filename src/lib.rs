@@ -0,0 +1,68 @@
+//! Workflow engine: run YAML-defined workflows as a DAG of nodes, in-process.
+//!
+//! ```no_run
+//! # async fn demo() -> anyhow::Result<()> {
+//! use workflow_engine::{load_workflow, Engine};
+//!
+//! let workflow = load_workflow("examples/example.yaml".as_ref())?;
+//! let engine = Engine::builder()
+//!     .workflow(workflow)
+//!     .input("topic", serde_json::json!("rust"))
+//!     .build()?;
+//! let result = engine.run().await?;
+//! println!("{:?}", result.results());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Custom node types are added to an [`ExecutorRegistry`] and handed to the builder:
+//!
+//! ```no_run
+//! # use workflow_engine::{load_workflow, Engine, ExecutorRegistry, GlobalMemory, Node, NodeExecutor, NodeMemory, NodeOutput};
+//! struct CrmExecutor;
+//!
+//! #[async_trait::async_trait]
+//! impl NodeExecutor for CrmExecutor {
+//!     async fn execute(&self, node: &Node, _: &GlobalMemory, _: &NodeMemory) -> anyhow::Result<NodeOutput> {
+//!         Ok(NodeOutput { status: "success".into(), output: node.params.clone() })
+//!     }
+//! }
+//!
+//! # async fn demo() -> anyhow::Result<()> {
+//! let executors = ExecutorRegistry::new().with("our_crm", CrmExecutor);
+//! let engine = Engine::builder()
+//!     .workflow(load_workflow("flows/crm.yaml".as_ref())?)
+//!     .executor_registry(executors)
+//!     .build()?;
+//! engine.run().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod schema;
+pub mod memory;
+pub mod storage;
+pub mod template;
+pub mod inputs;
+pub mod loader;
+pub mod compose;
+pub mod plan;
+pub mod migrate;
+pub mod nodes;
+pub mod engine;
+pub mod events;
+pub mod http_pool;
+
+pub mod server;
+pub mod worker;
+pub mod coordinator;
+
+pub use engine::{Engine, EngineBuilder, RunResult};
+pub use events::{EventBus, OutputStream, RunEvent};
+pub use http_pool::{HttpConfig, HttpPool, PooledResponse};
+pub use loader::load_workflow;
+pub use memory::{GlobalMemory, JournalEntry, NodeMemory, NodeOutput};
+pub use nodes::{ExecutorRegistry, NodeExecutor};
+pub use schema::{Node, Workflow};
+pub use storage::{MemoryBackend, MemoryStore};
+pub use template::TemplateEngine;
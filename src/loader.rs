@@ -0,0 +1,79 @@
+use crate::compose::Composer;
+use crate::schema::Workflow;
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+/// Read and parse a workflow file, resolving file references relative to it
+pub fn load_workflow(path: &Path) -> Result<Workflow> {
+    load_with_sources(path).map(|(workflow, _)| workflow)
+}
+
+/// Parse workflow YAML whose relative file references are based at `base_dir`
+fn parse_composed(content: &str, base_dir: &Path, composer: &mut Composer) -> Result<Workflow> {
+    let doc: serde_yaml::Value = serde_yaml::from_str(content)
+        .context("Failed to parse YAML workflow")?;
+    let doc = composer.compose(doc, base_dir)?;
+    serde_yaml::from_value(doc).context("Failed to parse YAML workflow")
+}
+
+/// Parse a workflow received as data rather than read from a file, such as a
+/// coordinator submission. It is migrated and composed like a file, but may not
+/// `include:` files from this host.
+pub fn parse_workflow_document(doc: serde_yaml::Value) -> Result<Workflow> {
+    if doc.get("include").is_some() {
+        anyhow::bail!("Submitted workflows cannot use 'include'; submit the composed workflow");
+    }
+    let mut composer = Composer::new(None);
+    let doc = composer.compose(doc, Path::new("."))?;
+    serde_yaml::from_value(doc).context("Failed to parse workflow")
+}
+
+fn load_with_sources(path: &Path) -> Result<(Workflow, Vec<PathBuf>)> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read file `{:?}`", path))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut composer = Composer::new(Some(path));
+    let mut workflow = parse_composed(&content, base_dir, &mut composer)?;
+    workflow.source = Some(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    Ok((workflow, composer.sources().to_vec()))
+}
+
+/// Modification times of every file a cached workflow was composed from
+type Sources = Vec<(PathBuf, Option<SystemTime>)>;
+
+/// Parsed workflows keyed by canonical path, reloaded when any source changes
+fn cache() -> &'static DashMap<PathBuf, (Sources, Workflow)> {
+    static CACHE: OnceLock<DashMap<PathBuf, (Sources, Workflow)>> = OnceLock::new();
+    CACHE.get_or_init(DashMap::new)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Like `load_workflow`, but reuses the parsed result while its files are unchanged
+pub fn load_workflow_cached(path: &Path) -> Result<Workflow> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("Workflow file `{:?}` not found", path))?;
+
+    if let Some(entry) = cache().get(&canonical) {
+        if entry.0.iter().all(|(source, mtime)| modified(source) == *mtime) {
+            return Ok(entry.1.clone());
+        }
+    }
+
+    let (workflow, sources) = load_with_sources(&canonical)?;
+    let sources = sources
+        .into_iter()
+        .map(|source| {
+            let mtime = modified(&source);
+            (source, mtime)
+        })
+        .collect();
+    cache().insert(canonical, (sources, workflow.clone()));
+    Ok(workflow)
+}
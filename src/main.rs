@@ -1,18 +1,9 @@
-mod schema;
-mod memory;
-mod template;
-mod nodes;
-mod engine;
-
-mod server;
-mod worker;
-mod coordinator;
-
 use clap::{Parser, Subcommand, ValueEnum};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
-use std::fs;
-use engine::Engine;
+use workflow_engine::{coordinator, inputs, loader, migrate, nodes, schema, server, worker};
+use workflow_engine::{Engine, ExecutorRegistry, MemoryBackend};
+use workflow_engine::nodes::runtime::RuntimeTable;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 enum OutputFormat {
@@ -42,6 +33,34 @@ struct Cli {
     /// Output format
     #[arg(short = 'o', long, value_enum, default_value_t = OutputFormat::Pretty)]
     format: OutputFormat,
+
+    /// Memory backend: memory, json:<dir> or sqlite:<file>
+    #[arg(long, value_name = "BACKEND", default_value = "memory")]
+    memory: MemoryBackend,
+
+    /// Run ID used to namespace persisted memory (random if omitted)
+    #[arg(long, value_name = "ID")]
+    run_id: Option<String>,
+
+    /// Session ID whose conversation variables persist across runs
+    #[arg(long, value_name = "ID")]
+    session: Option<String>,
+
+    /// Include full debug memory (globals, node outputs, journal) next to declared outputs
+    #[arg(long)]
+    debug: bool,
+
+    /// Plugins file declaring external node types
+    #[arg(long, value_name = "FILE", global = true)]
+    plugins: Option<PathBuf>,
+
+    /// Script runtimes file (language -> command, args, extension, venv)
+    #[arg(long, value_name = "FILE", global = true)]
+    runtimes: Option<PathBuf>,
+
+    /// Directory for environments built from script `requirements:`; share it between workers
+    #[arg(long, value_name = "DIR", global = true)]
+    env_cache: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -59,12 +78,32 @@ enum Commands {
         /// Output format
         #[arg(short = 'o', long, value_enum, default_value_t = OutputFormat::Pretty)]
         format: OutputFormat,
+
+        /// Memory backend: memory, json:<dir> or sqlite:<file>
+        #[arg(long, value_name = "BACKEND", default_value = "memory")]
+        memory: MemoryBackend,
+
+        /// Run ID used to namespace persisted memory (random if omitted)
+        #[arg(long, value_name = "ID")]
+        run_id: Option<String>,
+
+        /// Session ID whose conversation variables persist across runs
+        #[arg(long, value_name = "ID")]
+        session: Option<String>,
+
+        /// Include full debug memory (globals, node outputs, journal) next to declared outputs
+        #[arg(long)]
+        debug: bool,
     },
     /// Start the webhook server
     Serve {
         /// Port to listen on
         #[arg(short, long, default_value = "3000")]
         port: u16,
+
+        /// Memory backend: memory, json:<dir> or sqlite:<file>
+        #[arg(long, value_name = "BACKEND", default_value = "memory")]
+        memory: MemoryBackend,
     },
     /// Start a distributed worker
     Worker {
@@ -85,6 +124,10 @@ enum Commands {
         /// Port to listen on
         #[arg(short, long, default_value = "8080")]
         port: u16,
+
+        /// Memory backend used to keep job results across restarts
+        #[arg(long, value_name = "BACKEND", default_value = "memory")]
+        memory: MemoryBackend,
     },
     /// Submit a workflow to the coordinator
     Submit {
@@ -95,6 +138,28 @@ enum Commands {
         /// Coordinator URL
         #[arg(short, long, default_value = "http://localhost:8080")]
         coordinator: String,
+
+        /// Input parameters in key=value format
+        #[arg(short, long, value_name = "KEY=VALUE")]
+        input: Vec<String>,
+    },
+    /// Show the declared inputs of a workflow
+    Describe {
+        /// Path to the workflow YAML file
+        #[arg(short, long, value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// Print the JSON Schema of the workflow DSL (for editor validation and autocomplete)
+    Schema,
+    /// Rewrite a workflow (and the files it includes) to the current schema version
+    Migrate {
+        /// Path to the workflow YAML file
+        #[arg(short, long, value_name = "FILE")]
+        file: PathBuf,
+
+        /// Print the migrated YAML instead of rewriting the files
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
@@ -107,14 +172,19 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Some(Commands::Serve { port }) => {
-            server::run_server(port).await?;
+        Some(Commands::Serve { port, memory }) => {
+            let executors = executors(&cli.plugins, &cli.runtimes, &cli.env_cache).await?;
+            server::run_server(port, memory, executors).await?;
         }
-        Some(Commands::Run { file, input, format }) => {
-            run_workflow(file, input, format).await?;
+        Some(Commands::Run { file, input, format, memory, run_id, session, debug }) => {
+            let executors = executors(&cli.plugins, &cli.runtimes, &cli.env_cache).await?;
+            let options = RunOptions { format, backend: memory, run_id, session, debug, executors };
+            run_workflow(file, input, options).await?;
         }
-        Some(Commands::Coordinator { port }) => {
-            coordinator::run_coordinator(port).await?;
+        Some(Commands::Coordinator { port, memory }) => {
+            // Plugin types are needed to validate submissions
+            let executors = executors(&cli.plugins, &cli.runtimes, &cli.env_cache).await?;
+            coordinator::run_coordinator(port, memory, executors).await?;
         }
         Some(Commands::Worker { id, port, coordinator }) => {
             // Start worker
@@ -129,16 +199,37 @@ async fn main() -> Result<()> {
                 });
             }
             
-            worker::run_worker(id, port).await?;
+            let executors = executors(&cli.plugins, &cli.runtimes, &cli.env_cache).await?;
+            worker::run_worker(id, port, executors).await?;
+        }
+        Some(Commands::Submit { file, coordinator, input }) => {
+            submit_workflow(file, coordinator, input).await?;
+        }
+        Some(Commands::Describe { file }) => {
+            let workflow = loader::load_workflow(&file)?;
+            print!("{}", inputs::describe(&workflow, &file.to_string_lossy()));
         }
-        Some(Commands::Submit { file, coordinator }) => {
-            submit_workflow(file, coordinator).await?;
+        Some(Commands::Schema) => {
+            let executors = executors(&cli.plugins, &cli.runtimes, &cli.env_cache).await?;
+            println!("{}", serde_json::to_string_pretty(&schema::json_schema(&executors))?);
+        }
+        Some(Commands::Migrate { file, dry_run }) => {
+            migrate_workflow(&file, dry_run)?;
         }
         None => {
             // Default behavior: check if file arg is present
             if let Some(file) = cli.file {
                 let input = cli.input.unwrap_or_default();
-                run_workflow(file, input, cli.format).await?;
+                let executors = executors(&cli.plugins, &cli.runtimes, &cli.env_cache).await?;
+                let options = RunOptions {
+                    format: cli.format,
+                    backend: cli.memory,
+                    run_id: cli.run_id,
+                    session: cli.session,
+                    debug: cli.debug,
+                    executors,
+                };
+                run_workflow(file, input, options).await?;
             } else {
                 // Print help if no args
                 use clap::CommandFactory;
@@ -150,32 +241,79 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn run_workflow(file: PathBuf, input: Vec<String>, format: OutputFormat) -> Result<()> {
-    println!("🚀 Loading workflow from: {:?}", file);
+/// Node types for the subcommands that run or check nodes; only these start plugin processes
+async fn executors(
+    plugins: &Option<PathBuf>,
+    runtimes: &Option<PathBuf>,
+    env_cache: &Option<PathBuf>,
+) -> Result<ExecutorRegistry> {
+    let executors = ExecutorRegistry::new();
+    if runtimes.is_some() || env_cache.is_some() {
+        let mut table = RuntimeTable::builtin();
+        if let Some(path) = runtimes {
+            table.load_overrides(path)?;
+        }
+        if let Some(dir) = env_cache {
+            table.set_env_cache(dir);
+        }
+        executors.register("script", nodes::ScriptExecutor::new(table));
+    }
+    if let Some(plugins) = plugins {
+        nodes::load_plugins(&executors, plugins).await?;
+    }
+    Ok(executors)
+}
+
+/// How a CLI run is executed and reported
+struct RunOptions {
+    format: OutputFormat,
+    backend: MemoryBackend,
+    run_id: Option<String>,
+    session: Option<String>,
+    debug: bool,
+    executors: ExecutorRegistry,
+}
 
-    let content = fs::read_to_string(&file)
-        .with_context(|| format!("Could not read file `{:?}`", file))?;
+async fn run_workflow(file: PathBuf, input: Vec<String>, options: RunOptions) -> Result<()> {
+    let RunOptions { format, backend, run_id, session, debug, executors } = options;
+    println!("🚀 Loading workflow from: {:?}", file);
 
-    let mut workflow: schema::Workflow = serde_yaml::from_str(&content)
-        .context("Failed to parse YAML workflow")?;
+    let workflow = loader::load_workflow(&file)?;
 
-    // Override/Add globals from CLI
-    for inp in input {
-        if let Some((key, value_str)) = inp.split_once('=') {
-            let value = serde_json::from_str(value_str)
-                .unwrap_or_else(|_| serde_json::Value::String(value_str.to_string()));
-            
-            workflow.global.insert(key.to_string(), value);
-        } else {
-            log::warn!("Invalid input format: {}", inp);
+    // CLI inputs are parsed by declared type, then validated by the builder
+    let provided = inputs::parse_cli_inputs(&workflow, &input)?;
+    let mut builder = Engine::builder()
+        .workflow(workflow)
+        .inputs(provided)
+        .memory(backend.clone())
+        .executor_registry(executors);
+    if let Some(run_id) = run_id {
+        builder = builder.run_id(run_id);
+    }
+    if let Some(session) = &session {
+        if !backend.is_persistent() {
+            log::warn!(
+                "Session '{}' is kept in memory and lost when this run exits; use --memory json:<dir> or sqlite:<file> to keep it",
+                session
+            );
         }
+        builder = builder.session(session.clone());
     }
+    let engine = builder.build()?;
+    let workflow = engine.workflow();
+    let run_id = engine.run_id().to_string();
 
     match format {
         OutputFormat::Pretty | OutputFormat::Markdown => {
             println!("✅ Workflow parsed: {}", workflow.name);
             println!("📊 Global vars: {:?}", workflow.global);
             println!("🔢 Nodes count: {}", workflow.nodes.len());
+            if backend.is_persistent() {
+                println!("💾 Memory: {} (run id: {})", backend, run_id);
+            }
+            if let Some(session) = &session {
+                println!("💬 Session: {}", session);
+            }
             println!();
         },
         OutputFormat::Json => {
@@ -184,9 +322,12 @@ async fn run_workflow(file: PathBuf, input: Vec<String>, format: OutputFormat) -
     }
 
     // Execute the workflow
-    let engine = Engine::new(workflow);
     engine.execute().await?;
 
+    let outputs = engine.resolve_outputs()?;
+    // Declared outputs replace the full memory dump unless --debug asks for it
+    let show_memory = debug || outputs.is_none();
+
     match format {
         OutputFormat::Pretty => {
             println!();
@@ -194,35 +335,84 @@ async fn run_workflow(file: PathBuf, input: Vec<String>, format: OutputFormat) -
             
             println!("\n📊 Final Execution Summary:");
             println!("----------------------------------------");
-            
-            println!("Global Memory:");
-            let globals = engine.get_global_memory().get_all();
-            if globals.is_empty() {
-                println!("  (empty)");
-            } else {
-                for (k, v) in globals {
-                    println!("  {}: {}", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+
+            if let Some(outputs) = &outputs {
+                println!("Outputs:");
+                for (k, v) in outputs {
+                    println!("  {}: {}", k, serde_json::to_string_pretty(v).unwrap_or_default());
                 }
             }
+
+            if show_memory {
+                if outputs.is_some() {
+                    println!();
+                }
+                println!("Global Memory:");
+                let globals = engine.get_global_memory().get_all()?;
+                if globals.is_empty() {
+                    println!("  (empty)");
+                } else {
+                    for (k, v) in globals {
+                        println!("  {}: {}", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+                    }
+                }
             
-            println!("\nNode Outputs:");
-            let outputs = engine.get_node_memory().get_all_values();
-            if outputs.is_empty() {
-                println!("  (empty)");
-            } else {
-                for (k, v) in outputs {
-                    println!("  {}: {}", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+                if session.is_some() {
+                    println!("\nConversation:");
+                    for (k, v) in engine.get_global_memory().get_all_conversation()? {
+                        println!("  {}: {}", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+                    }
+                }
+
+                println!("\nNode Outputs:");
+                let outputs = engine.get_node_memory().get_all_values()?;
+                if outputs.is_empty() {
+                    println!("  (empty)");
+                } else {
+                    for (k, v) in outputs {
+                        println!("  {}: {}", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+                    }
+                }
+
+                println!("\nMemory Journal:");
+                let journal: Vec<_> = engine.get_global_memory().journal().into_iter().filter(|e| e.node.is_some()).collect();
+                if journal.is_empty() {
+                    println!("  (no writes by nodes)");
+                } else {
+                    for entry in journal {
+                        println!(
+                            "  [{}] {}.{}: {} → {}",
+                            entry.node.unwrap_or_default(),
+                            entry.scope,
+                            entry.key,
+                            format_journal_value(&entry.old),
+                            format_journal_value(&entry.new)
+                        );
+                    }
                 }
             }
             println!("----------------------------------------");
         },
         OutputFormat::Json => {
             let mut result_json = serde_json::Map::new();
-            let globals_map: serde_json::Map<String, serde_json::Value> = engine.get_global_memory().get_all().into_iter().collect();
-            let outputs_map: serde_json::Map<String, serde_json::Value> = engine.get_node_memory().get_all_values().into_iter().collect();
+            result_json.insert("run_id".to_string(), serde_json::Value::String(run_id.clone()));
+            if let Some(outputs) = &outputs {
+                result_json.insert("outputs".to_string(), serde_json::Value::Object(outputs.clone()));
+            }
+
+            if show_memory {
+                let globals_map: serde_json::Map<String, serde_json::Value> = engine.get_global_memory().get_all()?.into_iter().collect();
+                let outputs_map: serde_json::Map<String, serde_json::Value> = engine.get_node_memory().get_all_values()?.into_iter().collect();
             
-            result_json.insert("global_memory".to_string(), serde_json::Value::Object(globals_map));
-            result_json.insert("node_outputs".to_string(), serde_json::Value::Object(outputs_map));
+                result_json.insert("global_memory".to_string(), serde_json::Value::Object(globals_map));
+                if let Some(session) = &session {
+                    let conversation_map: serde_json::Map<String, serde_json::Value> = engine.get_global_memory().get_all_conversation()?.into_iter().collect();
+                    result_json.insert("session".to_string(), serde_json::Value::String(session.clone()));
+                    result_json.insert("conversation".to_string(), serde_json::Value::Object(conversation_map));
+                }
+                result_json.insert("node_outputs".to_string(), serde_json::Value::Object(outputs_map));
+                result_json.insert("journal".to_string(), serde_json::to_value(engine.get_global_memory().journal()).unwrap_or_default());
+            }
             
             println!("{}", serde_json::to_string_pretty(&serde_json::Value::Object(result_json)).unwrap_or_default());
         },
@@ -231,24 +421,60 @@ async fn run_workflow(file: PathBuf, input: Vec<String>, format: OutputFormat) -
             println!("✨ Workflow execution completed!");
             
             println!("\n# Final Execution Summary");
-            
-            println!("\n## Global Memory");
-            let globals = engine.get_global_memory().get_all();
-            if globals.is_empty() {
-                println!("  *(empty)*");
-            } else {
-                for (k, v) in globals {
-                    println!("### `{}`\n```json\n{}\n```", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+
+            if let Some(outputs) = &outputs {
+                println!("\n## Outputs");
+                for (k, v) in outputs {
+                    println!("### `{}`\n```json\n{}\n```", k, serde_json::to_string_pretty(v).unwrap_or_default());
                 }
             }
             
-            println!("\n## Node Outputs");
-            let outputs = engine.get_node_memory().get_all_values();
-            if outputs.is_empty() {
-                println!("  *(empty)*");
-            } else {
-                for (k, v) in outputs {
-                    println!("### `{}`\n```json\n{}\n```", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+            if show_memory {
+                println!("\n## Global Memory");
+                let globals = engine.get_global_memory().get_all()?;
+                if globals.is_empty() {
+                    println!("  *(empty)*");
+                } else {
+                    for (k, v) in globals {
+                        println!("### `{}`\n```json\n{}\n```", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+                    }
+                }
+            
+                if session.is_some() {
+                    println!("\n## Conversation");
+                    for (k, v) in engine.get_global_memory().get_all_conversation()? {
+                        println!("### `{}`\n```json\n{}\n```", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+                    }
+                }
+
+                println!("\n## Node Outputs");
+                let outputs = engine.get_node_memory().get_all_values()?;
+                if outputs.is_empty() {
+                    println!("  *(empty)*");
+                } else {
+                    for (k, v) in outputs {
+                        println!("### `{}`\n```json\n{}\n```", k, serde_json::to_string_pretty(&v).unwrap_or_default());
+                    }
+                }
+
+                println!("\n## Memory Journal");
+                let journal: Vec<_> = engine.get_global_memory().journal().into_iter().filter(|e| e.node.is_some()).collect();
+                if journal.is_empty() {
+                    println!("  *(no writes by nodes)*");
+                } else {
+                    println!("| # | Node | Variable | Old | New |");
+                    println!("|---|------|----------|-----|-----|");
+                    for entry in journal {
+                        println!(
+                            "| {} | `{}` | `{}.{}` | `{}` | `{}` |",
+                            entry.seq,
+                            entry.node.unwrap_or_default(),
+                            entry.scope,
+                            entry.key,
+                            format_journal_value(&entry.old),
+                            format_journal_value(&entry.new)
+                        );
+                    }
                 }
             }
         }
@@ -257,6 +483,21 @@ async fn run_workflow(file: PathBuf, input: Vec<String>, format: OutputFormat) -
     Ok(())
 }
 
+/// Compact one-line rendering of a journaled value (`∅` when absent)
+fn format_journal_value(value: &Option<serde_json::Value>) -> String {
+    match value {
+        Some(v) => {
+            let s = serde_json::to_string(v).unwrap_or_default();
+            if s.chars().count() > 80 {
+                format!("{}…", s.chars().take(80).collect::<String>())
+            } else {
+                s
+            }
+        }
+        None => "∅".to_string(),
+    }
+}
+
 async fn register_worker(coordinator_url: String, worker_url: String, worker_id: String) -> Result<()> {
     log::info!("📝 Registering worker {} with coordinator...", worker_id);
     
@@ -279,24 +520,27 @@ async fn register_worker(coordinator_url: String, worker_url: String, worker_id:
     Ok(())
 }
 
-async fn submit_workflow(file: PathBuf, coordinator_url: String) -> Result<()> {
+async fn submit_workflow(file: PathBuf, coordinator_url: String, input: Vec<String>) -> Result<()> {
     println!("📤 Submitting workflow to coordinator...");
     
-    let content = fs::read_to_string(&file)
-        .with_context(|| format!("Failed to read file: {:?}", file))?;
-    
-    let workflow: schema::Workflow = serde_yaml::from_str(&content)?;
+    let workflow = loader::load_workflow(&file)?;
+    let inputs = inputs::parse_cli_inputs(&workflow, &input)?;
     
     let client = reqwest::Client::new();
-    let resp: serde_json::Value = client
+    let resp = client
         .post(format!("{}/submit", coordinator_url))
         .json(&serde_json::json!({
-            "workflow": workflow
+            "workflow": workflow,
+            "inputs": inputs
         }))
         .send()
-        .await?
-        .json()
         .await?;
+
+    if !resp.status().is_success() {
+        let status = resp.status();
+        anyhow::bail!("Coordinator rejected workflow ({}): {}", status, resp.text().await.unwrap_or_default());
+    }
+    let resp: serde_json::Value = resp.json().await?;
     
     println!("✅ Workflow submitted!");
     println!("   Job ID: {}", resp["job_id"]);
@@ -340,3 +584,33 @@ async fn submit_workflow(file: PathBuf, coordinator_url: String) -> Result<()> {
     
     Ok(())
 }
+
+fn migrate_workflow(file: &Path, dry_run: bool) -> Result<()> {
+    for migrated in migrate::migrate_file(file)? {
+        let path = migrated.path.display();
+        if !migrated.changed {
+            println!("✓ {} is already at schema version {}", path, migrate::CURRENT_VERSION);
+            continue;
+        }
+
+        if dry_run {
+            println!("# {} (schema version {} -> {})", path, migrated.from, migrate::CURRENT_VERSION);
+            println!("{}", migrated.content);
+            continue;
+        }
+
+        // Keep the original around: comments and formatting are not preserved
+        let backup = migrated.path.with_extension("yaml.bak");
+        std::fs::copy(&migrated.path, &backup)
+            .with_context(|| format!("Failed to back up {}", path))?;
+        std::fs::write(&migrated.path, &migrated.content)
+            .with_context(|| format!("Failed to write {}", path))?;
+
+        println!("✅ Migrated {} from schema version {} to {} (backup: {})",
+            path, migrated.from, migrate::CURRENT_VERSION, backup.display());
+        for step in &migrated.applied {
+            println!("   - {}", step);
+        }
+    }
+    Ok(())
+}
@@ -1,40 +1,250 @@
+use crate::events::EventBus;
+use crate::http_pool::HttpPool;
+use crate::storage::{InMemoryStore, MemoryBackend, MemoryStore};
+use anyhow::{Context, Result};
 use dashmap::DashMap;
 use serde_json::Value;
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// One recorded write to a global or conversation variable
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub timestamp_ms: u64,
+    /// Node that made the write (`loop_id/step_id` inside loops); `None` for workflow setup
+    pub node: Option<String>,
+    pub scope: String,
+    pub key: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Entries kept in a run's journal; older ones are dropped
+pub const JOURNAL_LIMIT: usize = 10_000;
+
+/// The latest writes of a run
+#[derive(Default)]
+struct Journal {
+    entries: VecDeque<JournalEntry>,
+    next_seq: u64,
+}
+
+impl Journal {
+    fn push(&mut self, mut entry: JournalEntry) {
+        entry.seq = self.next_seq;
+        self.next_seq += 1;
+        if self.entries.len() == JOURNAL_LIMIT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
 
 /// Global memory shared across all nodes
-#[derive(Clone, Debug)]
+///
+/// Also carries the conversation variables of the current session, which
+/// outlive a single run when the session store is persistent.
+#[derive(Clone)]
 pub struct GlobalMemory {
-    data: Arc<DashMap<String, Value>>,
+    data: Arc<dyn MemoryStore>,
+    conversation: Arc<dyn MemoryStore>,
+    journal: Arc<Mutex<Journal>>,
+    /// One lock per `scope/key`, so a read-then-write of a key is not interleaved
+    key_locks: Arc<DashMap<String, Arc<Mutex<()>>>>,
+    /// Node on whose behalf writes are made, recorded in the journal
+    writer: Option<String>,
+    /// Where nodes publish live progress of the run
+    events: EventBus,
+    /// Outgoing HTTP with the run's per-host limits
+    http: HttpPool,
+    /// Sub-workflow files running above this run, outermost first
+    call_stack: Arc<Vec<PathBuf>>,
+    /// Backend and run the memory was opened from, for sub-runs to open theirs
+    backend: MemoryBackend,
+    run_id: String,
+}
+
+impl Default for GlobalMemory {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GlobalMemory {
     pub fn new() -> Self {
+        Self::with_store(Arc::new(InMemoryStore::new()))
+    }
+
+    pub fn with_store(store: Arc<dyn MemoryStore>) -> Self {
         Self {
-            data: Arc::new(DashMap::new()),
+            data: store,
+            conversation: Arc::new(InMemoryStore::new()),
+            journal: Arc::default(),
+            key_locks: Arc::default(),
+            writer: None,
+            events: EventBus::new(),
+            http: HttpPool::default(),
+            call_stack: Arc::default(),
+            backend: MemoryBackend::InMemory,
+            run_id: String::new(),
         }
     }
 
-    pub fn set(&self, key: String, value: Value) {
-        self.data.insert(key, value);
+    /// A handle to the same memory whose writes are attributed to `node_id`.
+    /// Nested calls (loop steps) are recorded as `parent/child`.
+    pub fn for_node(&self, node_id: &str) -> Self {
+        let writer = match &self.writer {
+            Some(parent) => format!("{}/{}", parent, node_id),
+            None => node_id.to_string(),
+        };
+        Self {
+            writer: Some(writer),
+            ..self.clone()
+        }
     }
 
-    pub fn get(&self, key: &str) -> Option<Value> {
-        self.data.get(key).map(|v| v.clone())
+    /// Attach the conversation store of a session
+    pub fn with_conversation(mut self, store: Arc<dyn MemoryStore>) -> Self {
+        self.conversation = store;
+        self
     }
 
-    pub fn get_all(&self) -> Vec<(String, Value)> {
-        self.data
-            .iter()
-            .map(|entry| (entry.key().clone(), entry.value().clone()))
-            .collect()
+    /// Publish run events to `events` instead of a bus of its own
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Send HTTP through `http` (and its host limits)
+    pub fn with_http(mut self, http: HttpPool) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn http(&self) -> &HttpPool {
+        &self.http
+    }
+
+    /// Mark this run as a sub-workflow called through `call_stack`
+    pub fn with_call_stack(mut self, call_stack: Vec<PathBuf>) -> Self {
+        self.call_stack = Arc::new(call_stack);
+        self
+    }
+
+    pub fn call_stack(&self) -> &[PathBuf] {
+        &self.call_stack
+    }
+
+    /// Memory for a sub-workflow called by this handle's node: fresh globals
+    /// and node outputs opened from the same backend, sharing the session,
+    /// events, journal and call stack of this run
+    pub fn sub_run(&self) -> Result<(GlobalMemory, NodeMemory)> {
+        let caller = self.writer.as_deref().unwrap_or("sub");
+        let run_id = format!("{}/{}/{}", self.run_id, caller, uuid::Uuid::new_v4());
+        let run_id = run_id.trim_start_matches('/').to_string();
+        let (global, nodes) = open_run_memory(&self.backend, &run_id)?;
+        Ok((
+            Self {
+                data: global.data,
+                run_id,
+                ..self.clone()
+            },
+            nodes,
+        ))
+    }
+
+    /// Node this handle acts for (`loop_id/step_id` inside loops)
+    pub fn node(&self) -> Option<&str> {
+        self.writer.as_deref()
+    }
+
+    pub fn set(&self, key: String, value: Value) -> Result<()> {
+        self.write("global", &self.data, key, Some(value)).map(|_| ())
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Value>> {
+        self.data.get(key)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<Option<Value>> {
+        self.write("global", &self.data, key.to_string(), None)
+    }
+
+    pub fn get_all(&self) -> Result<Vec<(String, Value)>> {
+        self.data.entries()
+    }
+
+    pub fn set_conversation(&self, key: String, value: Value) -> Result<()> {
+        self.write("conversation", &self.conversation, key, Some(value)).map(|_| ())
+    }
+
+    pub fn get_conversation(&self, key: &str) -> Result<Option<Value>> {
+        self.conversation.get(key)
+    }
+
+    pub fn remove_conversation(&self, key: &str) -> Result<Option<Value>> {
+        self.write("conversation", &self.conversation, key.to_string(), None)
+    }
+
+    pub fn get_all_conversation(&self) -> Result<Vec<(String, Value)>> {
+        self.conversation.entries()
+    }
+
+    /// The latest writes, in order (at most [`JOURNAL_LIMIT`]; `seq` keeps counting)
+    pub fn journal(&self) -> Vec<JournalEntry> {
+        self.journal.lock().unwrap().entries.iter().cloned().collect()
+    }
+
+    /// Set (`Some`) or remove (`None`) a variable and record the change.
+    /// Returns the previous value.
+    fn write(&self, scope: &str, store: &Arc<dyn MemoryStore>, key: String, value: Option<Value>) -> Result<Option<Value>> {
+        // Only writers of the same key wait for each other; the journal lock is
+        // not held across store I/O
+        let lock = self.key_locks.entry(format!("{}/{}", scope, key)).or_default().clone();
+        let _guard = lock.lock().unwrap();
+        let old = match &value {
+            Some(v) => {
+                let old = store.get(&key)?;
+                store.set(&key, v.clone()).map(|_| old)
+            }
+            None => store.remove(&key),
+        }
+        .with_context(|| format!("Failed to store {} variable '{}'", scope, key))?;
+
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        self.journal.lock().unwrap().push(JournalEntry {
+            seq: 0,
+            timestamp_ms,
+            node: self.writer.clone(),
+            scope: scope.to_string(),
+            key,
+            old: old.clone(),
+            new: value,
+        });
+        Ok(old)
+    }
+}
+
+impl std::fmt::Debug for GlobalMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.get_all().unwrap_or_default()).finish()
     }
 }
 
 /// Node output storage - stores results of each node execution
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct NodeMemory {
-    outputs: Arc<DashMap<String, NodeOutput>>,
+    outputs: Arc<dyn MemoryStore>,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -43,30 +253,194 @@ pub struct NodeOutput {
     pub output: Value,
 }
 
+impl Default for NodeMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NodeMemory {
     pub fn new() -> Self {
-        Self {
-            outputs: Arc::new(DashMap::new()),
-        }
+        Self::with_store(Arc::new(InMemoryStore::new()))
     }
 
-    pub fn set(&self, node_id: String, output: NodeOutput) {
-        self.outputs.insert(node_id, output);
+    pub fn with_store(store: Arc<dyn MemoryStore>) -> Self {
+        Self { outputs: store }
     }
 
-    #[allow(dead_code)]
-    pub fn get(&self, node_id: &str) -> Option<NodeOutput> {
-        self.outputs.get(node_id).map(|v| v.clone())
+    pub fn set(&self, node_id: String, output: NodeOutput) -> Result<()> {
+        self.outputs
+            .set(&node_id, serde_json::to_value(&output)?)
+            .with_context(|| format!("Failed to store output of node '{}'", node_id))
     }
 
-    pub fn get_output_value(&self, node_id: &str) -> Option<Value> {
-        self.outputs.get(node_id).map(|v| v.output.clone())
+    pub fn get(&self, node_id: &str) -> Result<Option<NodeOutput>> {
+        self.outputs
+            .get(node_id)?
+            .map(serde_json::from_value)
+            .transpose()
+            .with_context(|| format!("Stored output of node '{}' is not a node output", node_id))
     }
 
-    pub fn get_all_values(&self) -> std::collections::HashMap<String, Value> {
+    pub fn get_output_value(&self, node_id: &str) -> Result<Option<Value>> {
+        Ok(self.get(node_id)?.map(|o| o.output))
+    }
+
+    pub fn get_all(&self) -> Result<std::collections::HashMap<String, NodeOutput>> {
         self.outputs
-            .iter()
-            .map(|entry| (entry.key().clone(), entry.value().output.clone()))
+            .entries()?
+            .into_iter()
+            .map(|(k, v)| {
+                let output = serde_json::from_value(v)
+                    .with_context(|| format!("Stored output of node '{}' is not a node output", k))?;
+                Ok((k, output))
+            })
             .collect()
     }
+
+    pub fn get_all_values(&self) -> Result<std::collections::HashMap<String, Value>> {
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .map(|(k, o)| (k, o.output))
+            .collect())
+    }
+}
+
+impl std::fmt::Debug for NodeMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.get_all().unwrap_or_default()).finish()
+    }
+}
+
+/// Open the global and node memories of one run from `backend`
+pub fn open_run_memory(backend: &MemoryBackend, run_id: &str) -> Result<(GlobalMemory, NodeMemory)> {
+    let mut global = GlobalMemory::with_store(backend.open(&format!("{}/global", run_id))?);
+    global.backend = backend.clone();
+    global.run_id = run_id.to_string();
+    let nodes = NodeMemory::with_store(backend.open(&format!("{}/nodes", run_id))?);
+    Ok((global, nodes))
+}
+
+/// An open session: its conversation variables and the lock that
+/// serializes runs on it, so concurrent runs don't lose each other's updates
+#[derive(Clone)]
+pub struct Session {
+    pub conversation: Arc<dyn MemoryStore>,
+    pub lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// Sessions not opened for this long, and not in use, are closed.
+/// An in-memory session's variables are lost with it.
+pub const SESSION_IDLE: Duration = Duration::from_secs(3600);
+
+struct CachedSession {
+    session: Session,
+    last_used: Instant,
+}
+
+/// Sessions opened by this process, keyed by backend and session id
+fn sessions() -> &'static DashMap<String, CachedSession> {
+    static SESSIONS: OnceLock<DashMap<String, CachedSession>> = OnceLock::new();
+    SESSIONS.get_or_init(DashMap::new)
+}
+
+/// Drop cached sessions idle for `idle` that no engine holds any more
+fn evict_idle_sessions(idle: Duration) {
+    sessions().retain(|_, cached| cached.last_used.elapsed() < idle || Arc::strong_count(&cached.session.lock) > 1);
+}
+
+/// Open a session from `backend`, sharing one store and lock per session
+/// within the process.
+///
+/// With the in-memory backend the session lives as long as the process
+/// (e.g. across requests to the server) and is lost when it exits.
+pub fn open_session(backend: &MemoryBackend, session_id: &str) -> Result<Session> {
+    evict_idle_sessions(SESSION_IDLE);
+    let key = format!("{}#{}", backend, session_id);
+    if let Some(mut cached) = sessions().get_mut(&key) {
+        cached.last_used = Instant::now();
+        return Ok(cached.session.clone());
+    }
+    let conversation = backend.open(&format!("conversation/{}", session_id))?;
+    let session = sessions()
+        .entry(key)
+        .or_insert_with(|| CachedSession {
+            session: Session {
+                conversation,
+                lock: Arc::default(),
+            },
+            last_used: Instant::now(),
+        })
+        .session
+        .clone();
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn journal_records_node_and_old_value() {
+        let global = GlobalMemory::new();
+        global.set("a".to_string(), json!(1)).unwrap();
+        global.for_node("step").set("a".to_string(), json!(2)).unwrap();
+        global.for_node("step").remove("a").unwrap();
+
+        let journal = global.journal();
+        assert_eq!(journal.len(), 3);
+        assert_eq!((journal[0].node.as_deref(), &journal[0].old), (None, &None));
+        assert_eq!((journal[1].node.as_deref(), &journal[1].old), (Some("step"), &Some(json!(1))));
+        assert_eq!((&journal[2].old, &journal[2].new), (&Some(json!(2)), &None));
+    }
+
+    #[test]
+    fn concurrent_writes_to_one_key_chain_old_values() {
+        let global = GlobalMemory::new();
+        let writers: Vec<_> = (0..8)
+            .map(|id| {
+                let global = global.for_node(&format!("n{}", id));
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        global.set("shared".to_string(), json!(id * 1000 + i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let journal = global.journal();
+        assert_eq!(journal.len(), 800);
+        assert_eq!(journal[0].old, None);
+        for pair in journal.windows(2) {
+            assert_eq!(pair[1].old, pair[0].new, "entry {} saw a stale value", pair[1].seq);
+        }
+    }
+
+    #[test]
+    fn idle_sessions_are_evicted_unless_held() {
+        let backend = MemoryBackend::InMemory;
+        let held = open_session(&backend, "test-held").unwrap();
+        held.conversation.set("k", json!(1)).unwrap();
+        open_session(&backend, "test-idle").unwrap().conversation.set("k", json!(1)).unwrap();
+
+        evict_idle_sessions(Duration::ZERO);
+        assert_eq!(open_session(&backend, "test-held").unwrap().conversation.get("k").unwrap(), Some(json!(1)));
+        assert_eq!(open_session(&backend, "test-idle").unwrap().conversation.get("k").unwrap(), None);
+    }
+
+    #[test]
+    fn journal_is_capped() {
+        let global = GlobalMemory::new();
+        for i in 0..JOURNAL_LIMIT + 5 {
+            global.set("k".to_string(), json!(i)).unwrap();
+        }
+        let journal = global.journal();
+        assert_eq!(journal.len(), JOURNAL_LIMIT);
+        assert_eq!(journal[0].seq, 5);
+    }
 }
@@ -0,0 +1,268 @@
+//! Versioning of the workflow DSL.
+//!
+//! `version:` names the DSL schema a document was written for. Older documents
+//! are upgraded step by step on load (and by `migrate`) before deserialization.
+
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+/// Schema version written by `migrate` and expected by the engine
+pub const CURRENT_VERSION: &str = "2.0";
+
+/// Description and in-place rewrite of one schema upgrade
+type Migration = (&'static str, fn(&mut Mapping));
+
+/// Upgrade from the version before it; index 0 upgrades 1.x to 2.0
+const MIGRATIONS: &[Migration] = &[
+    ("alternate spellings replaced: assign `unset` -> `remove`, llm `json_object` -> `json`, input `boolean` -> `bool`", canonical_spellings),
+];
+
+/// Major schema version declared by a document; documents without `version` are 1.x
+pub fn schema_version(doc: &Value) -> Result<u32> {
+    let version = match doc.get("version") {
+        None | Some(Value::Null) => return Ok(1),
+        Some(Value::String(s)) => s.trim().to_string(),
+        Some(Value::Number(n)) => n.to_string(),
+        Some(other) => anyhow::bail!("Invalid workflow version: {:?}", other),
+    };
+    version
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u32>().ok())
+        .with_context(|| format!("Invalid workflow version '{}', expected e.g. \"{}\"", version, CURRENT_VERSION))
+}
+
+fn current_major() -> u32 {
+    CURRENT_VERSION.split('.').next().and_then(|m| m.parse().ok()).unwrap_or(1)
+}
+
+/// Upgrade `doc` in place to the current version, returning the applied steps
+pub fn migrate(doc: &mut Value) -> Result<Vec<&'static str>> {
+    let from = schema_version(doc)?;
+    let current = current_major();
+    if from > current {
+        anyhow::bail!(
+            "Workflow uses schema version {} but this engine supports up to {}",
+            from,
+            CURRENT_VERSION
+        );
+    }
+    let Value::Mapping(map) = doc else {
+        anyhow::bail!("Workflow document must be a mapping");
+    };
+
+    let mut applied = Vec::new();
+    for (description, step) in MIGRATIONS.iter().skip(from.saturating_sub(1) as usize) {
+        step(map);
+        applied.push(*description);
+    }
+    if from < current || !map.contains_key("version") {
+        map.insert("version".into(), CURRENT_VERSION.into());
+    }
+    Ok(applied)
+}
+
+/// Apply `f` to every node definition: `nodes`, `templates` and nested loop `steps`
+fn for_each_node(doc: &mut Mapping, f: &mut dyn FnMut(&mut Mapping)) {
+    fn visit(node: &mut Value, f: &mut dyn FnMut(&mut Mapping)) {
+        let Value::Mapping(map) = node else { return };
+        f(map);
+        if let Some(Value::Sequence(steps)) = map.get_mut("params").and_then(|p| p.get_mut("steps")) {
+            steps.iter_mut().for_each(|step| visit(step, f));
+        }
+    }
+
+    if let Some(Value::Sequence(nodes)) = doc.get_mut("nodes") {
+        nodes.iter_mut().for_each(|node| visit(node, f));
+    }
+    if let Some(Value::Mapping(templates)) = doc.get_mut("templates") {
+        templates.iter_mut().for_each(|(_, node)| visit(node, f));
+    }
+}
+
+/// Replace `from` with `to` where `value` is exactly `from`
+fn rename_value(value: Option<&mut Value>, from: &str, to: &str) {
+    if let Some(value) = value.filter(|v| v.as_str() == Some(from)) {
+        *value = to.into();
+    }
+}
+
+/// 1.x -> 2.0: 2.0 documents use one spelling per value; the executors still
+/// accept the old ones, so hand-written 2.0 files keep working
+fn canonical_spellings(doc: &mut Mapping) {
+    for_each_node(doc, &mut |node| {
+        let node_type = node.get("type").and_then(|t| t.as_str()).map(str::to_string);
+        let Some(Value::Mapping(params)) = node.get_mut("params") else { return };
+        match node_type.as_deref() {
+            Some("assign") => {
+                if let Some(Value::Sequence(assignments)) = params.get_mut("assignments") {
+                    for assignment in assignments {
+                        rename_value(assignment.get_mut("mode"), "unset", "remove");
+                    }
+                }
+            }
+            Some("llm") => rename_value(params.get_mut("response_format"), "json_object", "json"),
+            _ => {}
+        }
+    });
+    if let Some(Value::Sequence(inputs)) = doc.get_mut("inputs") {
+        for input in inputs {
+            rename_value(input.get_mut("type"), "boolean", "bool");
+        }
+    }
+}
+
+/// A file upgraded by `migrate_file`
+pub struct MigratedFile {
+    pub path: PathBuf,
+    pub from: u32,
+    pub applied: Vec<&'static str>,
+    /// Whether `content` differs from the file, e.g. only by the `version` it now declares
+    pub changed: bool,
+    /// Upgraded document as YAML
+    pub content: String,
+}
+
+/// Migrate a workflow file and, recursively, the fragments it `include:`s.
+/// Fragments without their own `version` are taken to share the includer's.
+pub fn migrate_file(path: &Path) -> Result<Vec<MigratedFile>> {
+    let mut files = Vec::new();
+    migrate_into(path, None, &mut files)?;
+    Ok(files)
+}
+
+fn migrate_into(path: &Path, inherited: Option<&Value>, files: &mut Vec<MigratedFile>) -> Result<()> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Workflow file `{:?}` not found", path))?;
+    if files.iter().any(|f| f.path == path) {
+        return Ok(());
+    }
+
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Could not read file `{:?}`", path))?;
+    let mut doc: Value = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse YAML in `{:?}`", path))?;
+    let unchanged = doc.clone();
+    if doc.get("version").is_none() {
+        if let (Some(version), Value::Mapping(map)) = (inherited, &mut doc) {
+            map.insert("version".into(), version.clone());
+        }
+    }
+    let original = doc.get("version").cloned();
+
+    let from = schema_version(&doc)?;
+    let applied = migrate(&mut doc)?;
+    let includes: Vec<String> = match doc.get("include") {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(other) => serde_yaml::from_value(other.clone()).unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    files.push(MigratedFile {
+        path: path.clone(),
+        from,
+        applied,
+        changed: doc != unchanged,
+        content: serde_yaml::to_string(&doc)?,
+    });
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for include in includes {
+        migrate_into(&base_dir.join(include), original.as_ref(), files)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn schema_version_parses_major() {
+        assert_eq!(schema_version(&yaml("name: x")).unwrap(), 1);
+        assert_eq!(schema_version(&yaml("version: '1.0'")).unwrap(), 1);
+        assert_eq!(schema_version(&yaml("version: 2")).unwrap(), 2);
+        assert_eq!(schema_version(&yaml("version: 1.5")).unwrap(), 1);
+        assert!(schema_version(&yaml("version: abc")).is_err());
+        assert!(schema_version(&yaml("version: [1]")).is_err());
+    }
+
+    #[test]
+    fn unversioned_document_is_upgraded() {
+        let mut doc = yaml(
+            r#"
+name: old
+inputs:
+  - {name: verbose, type: boolean}
+templates:
+  ask: {type: llm, params: {prompt: hi, response_format: json_object}}
+nodes:
+  - id: clear
+    type: assign
+    params: {assignments: [{key: a, mode: unset}, {key: b, value: 1}]}
+  - id: each
+    type: loop
+    params:
+      items: [1]
+      steps:
+        - {id: ask, type: llm, params: {prompt: "{{ loop.item }}", response_format: json_object}}
+"#,
+        );
+        let applied = migrate(&mut doc).unwrap();
+
+        assert_eq!(applied.len(), 1);
+        assert_eq!(doc["version"], Value::from(CURRENT_VERSION));
+        assert_eq!(doc["inputs"][0]["type"], yaml("bool"));
+        assert_eq!(doc["templates"]["ask"]["params"]["response_format"], yaml("json"));
+        assert_eq!(doc["nodes"][0]["params"]["assignments"], yaml("[{key: a, mode: remove}, {key: b, value: 1}]"));
+        assert_eq!(doc["nodes"][1]["params"]["steps"][0]["params"]["response_format"], yaml("json"));
+    }
+
+    #[test]
+    fn loader_upgrades_unversioned_files() {
+        let path = std::env::temp_dir().join(format!("workflow-engine-old-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "name: old\nnodes: [{id: a, type: assign, params: {assignments: [{key: a, mode: unset}]}}]\n").unwrap();
+
+        let workflow = crate::loader::load_workflow(&path).unwrap();
+        assert_eq!(workflow.version, CURRENT_VERSION);
+        assert_eq!(workflow.nodes[0].params["assignments"][0]["mode"], "remove");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn current_documents_are_left_alone() {
+        let text = "version: '2.0'\nnodes: [{id: a, type: assign, params: {assignments: [{key: a, mode: unset}]}}]";
+        let mut doc = yaml(text);
+        assert!(migrate(&mut doc).unwrap().is_empty());
+        assert_eq!(doc, yaml(text));
+    }
+
+    #[test]
+    fn migrate_rejects_newer_versions() {
+        let err = migrate(&mut yaml("version: '99.0'")).unwrap_err();
+        assert!(err.to_string().contains("schema version 99"), "{}", err);
+    }
+
+    #[test]
+    fn migrate_file_follows_includes_once() {
+        let dir = std::env::temp_dir().join(format!("workflow-engine-migrate-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.yaml"), "include: [part.yaml, part.yaml]\nname: main\n").unwrap();
+        std::fs::write(dir.join("part.yaml"), "global: {a: 1}\n").unwrap();
+        std::fs::write(dir.join("current.yaml"), "version: '2.0'\nname: current\n").unwrap();
+
+        let files = migrate_file(&dir.join("main.yaml")).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].path, dir.join("part.yaml").canonicalize().unwrap());
+        assert_eq!(files[1].from, 1);
+        assert!(files.iter().all(|f| f.changed && f.content.contains(CURRENT_VERSION)));
+        assert!(!migrate_file(&dir.join("current.yaml")).unwrap()[0].changed);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,13 +1,104 @@
-use crate::nodes::NodeExecutor;
+use crate::nodes::{params_schema, parse_params, NodeExecutor};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
 use serde_json::Value;
 
 pub struct AssignExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AssignParams {
+    pub assignments: Vec<Assignment>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Assignment {
+    /// Variable name; a dotted path like `user.profile.name` for `set_path`
+    pub key: String,
+    /// Strings are templated and parsed as JSON when possible;
+    /// optional for increment/decrement/pop/remove
+    #[serde(default)]
+    pub value: Option<Value>,
+    #[serde(default)]
+    pub mode: AssignMode,
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AssignMode {
+    #[default]
+    Set,
+    SetIfAbsent,
+    SetPath,
+    Append,
+    Extend,
+    Pop,
+    Merge,
+    Increment,
+    Decrement,
+    Remove,
+    Unset,
+}
+
+impl std::fmt::Display for AssignMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AssignMode::Set => "set",
+            AssignMode::SetIfAbsent => "set_if_absent",
+            AssignMode::SetPath => "set_path",
+            AssignMode::Append => "append",
+            AssignMode::Extend => "extend",
+            AssignMode::Pop => "pop",
+            AssignMode::Merge => "merge",
+            AssignMode::Increment => "increment",
+            AssignMode::Decrement => "decrement",
+            AssignMode::Remove => "remove",
+            AssignMode::Unset => "unset",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Which variables an assignment reads and writes
+#[derive(Deserialize, JsonSchema, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    #[default]
+    Global,
+    Conversation,
+}
+
+impl Scope {
+    fn get(&self, global: &GlobalMemory, key: &str) -> Result<Option<Value>> {
+        match self {
+            Scope::Global => global.get(key),
+            Scope::Conversation => global.get_conversation(key),
+        }
+    }
+
+    fn set(&self, global: &GlobalMemory, key: String, value: Value) -> Result<()> {
+        match self {
+            Scope::Global => global.set(key, value),
+            Scope::Conversation => global.set_conversation(key, value),
+        }
+    }
+
+    fn remove(&self, global: &GlobalMemory, key: &str) -> Result<Option<Value>> {
+        match self {
+            Scope::Global => global.remove(key),
+            Scope::Conversation => global.remove_conversation(key),
+        }
+    }
+}
+
 #[async_trait]
 impl NodeExecutor for AssignExecutor {
     async fn execute(
@@ -17,70 +108,331 @@ impl NodeExecutor for AssignExecutor {
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
         let template = TemplateEngine::new(global.clone(), nodes.clone());
-        
-        // Parse "assignments" parameter
-        // Format:
-        // assignments:
-        //   - key: "variable_name"
-        //     value: "some value"
-        //     mode: "set" | "append" (optional, default "set")
-        
-        let assignments_val = node.params
-            .get("assignments")
-            .context("Assign node requires 'assignments' parameter")?;
-            
-        let assignments = assignments_val.as_array()
-            .context("'assignments' must be an array")?;
 
+        let params: AssignParams = parse_params(node)?;
         let mut output_map = serde_json::Map::new();
+        // Values taken off by `pop`, by variable
+        let mut popped = serde_json::Map::new();
 
-        for assign in assignments {
-            let key = assign.get("key")
-                .and_then(|v| v.as_str())
-                .context("Assignment requires 'key'")?;
-                
-            let value_template = assign.get("value")
-                .context("Assignment requires 'value'")?;
-            
-            let mode = assign.get("mode")
-                .and_then(|v| v.as_str())
-                .unwrap_or("set");
+        for assign in &params.assignments {
+            let key = assign.key.as_str();
+            let mode = assign.mode;
+            let scope = assign.scope;
 
             // Render value
-            let rendered_value = if let Some(s) = value_template.as_str() {
-                let rendered = template.render(s)?;
-                // Try to parse as JSON, otherwise keep as string
-                serde_json::from_str(&rendered)
-                    .unwrap_or(Value::String(rendered))
+            let rendered_value = match &assign.value {
+                Some(Value::String(s)) => {
+                    let rendered = template.render(s)?;
+                    // Try to parse as JSON, otherwise keep as string
+                    Some(serde_json::from_str(&rendered)
+                        .unwrap_or(Value::String(rendered)))
+                }
+                Some(v) => Some(v.clone()),
+                None => None,
+            };
+
+            // set_path writes below the variable named by the first path segment
+            let (var, path) = if mode == AssignMode::SetPath {
+                let mut segments = key.split('.');
+                let var = segments.next().unwrap_or(key);
+                (var, segments.collect::<Vec<_>>())
             } else {
-                value_template.clone()
+                (key, Vec::new())
             };
 
-            match mode {
-                "set" => {
-                    global.set(key.to_string(), rendered_value.clone());
-                    output_map.insert(key.to_string(), rendered_value);
-                }
-                "append" => {
-                    // Get existing list or create new
-                    let mut list = global.get(key).unwrap_or(Value::Array(vec![]));
-                    if let Value::Array(ref mut arr) = list {
-                        arr.push(rendered_value.clone());
-                        global.set(key.to_string(), list.clone());
-                        output_map.insert(key.to_string(), list);
-                    } else {
-                        log::warn!("Cannot append to non-array variable '{}'", key);
-                    }
+            let current = scope.get(global, var)?;
+            if mode == AssignMode::Pop {
+                let last = current.as_ref().and_then(Value::as_array).and_then(|list| list.last());
+                popped.insert(var.to_string(), last.cloned().unwrap_or(Value::Null));
+            }
+            let updated = apply(mode, var, &path, current, rendered_value)
+                .with_context(|| format!("Assignment '{}' ({}) failed", key, mode))?;
+
+            match updated {
+                Some(value) => {
+                    scope.set(global, var.to_string(), value.clone())?;
+                    output_map.insert(var.to_string(), value);
                 }
-                _ => {
-                    log::warn!("Unknown assignment mode '{}', skipping", mode);
+                None => {
+                    scope.remove(global, var)?;
+                    output_map.insert(var.to_string(), Value::Null);
                 }
             }
         }
 
+        if !popped.is_empty() {
+            output_map.insert("popped".to_string(), Value::Object(popped));
+        }
+
         Ok(NodeOutput {
             status: "success".to_string(),
             output: Value::Object(output_map),
         })
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<AssignParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        parse_params::<AssignParams>(node).map(drop)
+    }
+}
+
+/// Compute the new value of a variable; `None` means the variable is removed
+fn apply(
+    mode: AssignMode,
+    key: &str,
+    path: &[&str],
+    current: Option<Value>,
+    value: Option<Value>,
+) -> Result<Option<Value>> {
+    let require_value = || value.clone().with_context(|| format!("Mode '{}' requires 'value'", mode));
+
+    match mode {
+        AssignMode::Set => Ok(Some(require_value()?)),
+        AssignMode::SetIfAbsent => match current {
+            Some(existing) => Ok(Some(existing)),
+            None => Ok(Some(require_value()?)),
+        },
+        AssignMode::Append => {
+            let mut list = expect_array(key, current)?;
+            list.push(require_value()?);
+            Ok(Some(Value::Array(list)))
+        }
+        AssignMode::Extend => {
+            let mut list = expect_array(key, current)?;
+            match require_value()? {
+                Value::Array(items) => list.extend(items),
+                other => anyhow::bail!("Cannot extend '{}' with non-array value {}", key, other),
+            }
+            Ok(Some(Value::Array(list)))
+        }
+        AssignMode::Pop => {
+            let mut list = expect_array(key, current)?;
+            list.pop();
+            Ok(Some(Value::Array(list)))
+        }
+        AssignMode::Merge => {
+            let mut target = match current {
+                Some(obj @ Value::Object(_)) => obj,
+                None | Some(Value::Null) => Value::Object(serde_json::Map::new()),
+                Some(other) => anyhow::bail!("Cannot merge into non-object variable '{}' ({})", key, type_name(&other)),
+            };
+            let patch = require_value()?;
+            if !patch.is_object() {
+                anyhow::bail!("Cannot merge non-object value {} into '{}'", patch, key);
+            }
+            deep_merge(&mut target, patch);
+            Ok(Some(target))
+        }
+        AssignMode::Increment | AssignMode::Decrement => {
+            let current = match current {
+                Some(Value::Number(n)) => n,
+                None | Some(Value::Null) => serde_json::Number::from(0),
+                Some(other) => anyhow::bail!("Cannot {} non-numeric variable '{}' ({})", mode, key, type_name(&other)),
+            };
+            let step = match value {
+                Some(Value::Number(n)) => n,
+                None => serde_json::Number::from(1),
+                Some(other) => anyhow::bail!("Step for {} must be a number, got {}", mode, other),
+            };
+            Ok(Some(add_numbers(&current, &step, mode == AssignMode::Decrement)?))
+        }
+        AssignMode::Remove | AssignMode::Unset => Ok(None),
+        AssignMode::SetPath => {
+            if path.is_empty() {
+                anyhow::bail!("Mode 'set_path' requires a dotted key like 'user.profile.name'");
+            }
+            let mut root = current.unwrap_or_else(|| Value::Object(serde_json::Map::new()));
+            set_at_path(&mut root, key, path, require_value()?)?;
+            Ok(Some(root))
+        }
+    }
+}
+
+fn expect_array(key: &str, current: Option<Value>) -> Result<Vec<Value>> {
+    match current {
+        Some(Value::Array(arr)) => Ok(arr),
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(other) => anyhow::bail!("Variable '{}' is not an array ({})", key, type_name(&other)),
+    }
+}
+
+fn add_numbers(a: &serde_json::Number, b: &serde_json::Number, subtract: bool) -> Result<Value> {
+    if let (Some(x), Some(y)) = (a.as_i64(), b.as_i64()) {
+        let result = if subtract { x.checked_sub(y) } else { x.checked_add(y) };
+        if let Some(n) = result {
+            return Ok(Value::from(n));
+        }
+    }
+
+    let x = a.as_f64().context("Number out of range")?;
+    let y = b.as_f64().context("Number out of range")?;
+    let result = if subtract { x - y } else { x + y };
+    serde_json::Number::from_f64(result)
+        .map(Value::Number)
+        .context("Arithmetic produced a non-finite number")
+}
+
+/// Recursively merge `patch` into `target`; non-object values are replaced
+fn deep_merge(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target_map), Value::Object(patch_map)) => {
+            for (k, v) in patch_map {
+                match target_map.get_mut(&k) {
+                    Some(existing) => deep_merge(existing, v),
+                    None => {
+                        target_map.insert(k, v);
+                    }
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+/// Write `value` at `path` below `root`, creating intermediate objects
+fn set_at_path(root: &mut Value, key: &str, path: &[&str], value: Value) -> Result<()> {
+    let mut current = root;
+    for (i, segment) in path.iter().enumerate() {
+        let is_last = i == path.len() - 1;
+
+        if let Value::Array(arr) = current {
+            let index: usize = segment
+                .parse()
+                .with_context(|| format!("'{}' is not a valid index into array at '{}'", segment, key))?;
+            let slot = arr
+                .get_mut(index)
+                .with_context(|| format!("Index {} out of bounds in '{}'", index, key))?;
+            if is_last {
+                *slot = value;
+                return Ok(());
+            }
+            current = slot;
+            continue;
+        }
+
+        if current.is_null() {
+            *current = Value::Object(serde_json::Map::new());
+        }
+        let map = match current {
+            Value::Object(map) => map,
+            other => anyhow::bail!("Cannot set '{}' below non-object value ({}) in '{}'", segment, type_name(other), key),
+        };
+
+        if is_last {
+            map.insert(segment.to_string(), value);
+            return Ok(());
+        }
+        current = map
+            .entry(segment.to_string())
+            .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    }
+    Ok(())
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn run(global: &GlobalMemory, assignments: Value) -> Result<Value> {
+        let node: Node = serde_json::from_value(json!({
+            "id": "assign", "type": "assign", "params": {"assignments": assignments}
+        }))?;
+        AssignExecutor.execute(&node, global, &NodeMemory::new()).await.map(|out| out.output)
+    }
+
+    fn memory(vars: Value) -> GlobalMemory {
+        let global = GlobalMemory::new();
+        for (key, value) in vars.as_object().unwrap() {
+            global.set(key.clone(), value.clone()).unwrap();
+        }
+        global
+    }
+
+    #[tokio::test]
+    async fn set_templates_and_parses_json() {
+        let global = memory(json!({"n": 2}));
+        run(&global, json!([
+            {"key": "a", "value": "{{ global.n }}"},
+            {"key": "b", "value": "hello"},
+            {"key": "a", "value": 9, "mode": "set_if_absent"},
+            {"key": "c", "value": 1, "mode": "set_if_absent"}
+        ])).await.unwrap();
+        assert_eq!(global.get("a").unwrap(), Some(json!(2)));
+        assert_eq!(global.get("b").unwrap(), Some(json!("hello")));
+        assert_eq!(global.get("c").unwrap(), Some(json!(1)));
+    }
+
+    #[tokio::test]
+    async fn append_extend_and_pop() {
+        let global = memory(json!({"list": [1]}));
+        let output = run(&global, json!([
+            {"key": "list", "value": 2, "mode": "append"},
+            {"key": "list", "value": [3, 4], "mode": "extend"},
+            {"key": "list", "mode": "pop"},
+            {"key": "empty", "mode": "pop"}
+        ])).await.unwrap();
+        assert_eq!(global.get("list").unwrap(), Some(json!([1, 2, 3])));
+        assert_eq!(output["popped"], json!({"list": 4, "empty": null}));
+        assert_eq!(global.get("empty").unwrap(), Some(json!([])));
+    }
+
+    #[tokio::test]
+    async fn increment_decrement_and_clear() {
+        let global = memory(json!({"count": 1, "ratio": 0.5, "gone": true}));
+        run(&global, json!([
+            {"key": "count", "value": 4, "mode": "increment"},
+            {"key": "ratio", "mode": "decrement"},
+            {"key": "fresh", "mode": "increment"},
+            {"key": "gone", "mode": "remove"}
+        ])).await.unwrap();
+        assert_eq!(global.get("count").unwrap(), Some(json!(5)));
+        assert_eq!(global.get("ratio").unwrap(), Some(json!(-0.5)));
+        assert_eq!(global.get("fresh").unwrap(), Some(json!(1)));
+        assert_eq!(global.get("gone").unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn merge_and_set_path() {
+        let global = memory(json!({"user": {"name": "a", "tags": ["x"]}}));
+        run(&global, json!([
+            {"key": "user", "value": {"age": 3}, "mode": "merge"},
+            {"key": "user.profile.city", "value": "Paris", "mode": "set_path"},
+            {"key": "user.tags.0", "value": "y", "mode": "set_path"}
+        ])).await.unwrap();
+        assert_eq!(
+            global.get("user").unwrap(),
+            Some(json!({"name": "a", "tags": ["y"], "age": 3, "profile": {"city": "Paris"}}))
+        );
+    }
+
+    #[tokio::test]
+    async fn type_mismatches_fail() {
+        let global = memory(json!({"text": "abc", "list": [1]}));
+        for (assignment, message) in [
+            (json!({"key": "text", "value": 1, "mode": "append"}), "is not an array (string)"),
+            (json!({"key": "text", "mode": "increment"}), "non-numeric variable 'text'"),
+            (json!({"key": "list", "value": 1, "mode": "extend"}), "non-array value"),
+            (json!({"key": "list", "value": {"a": 1}, "mode": "merge"}), "non-object variable 'list'"),
+            (json!({"key": "x", "mode": "set"}), "requires 'value'"),
+        ] {
+            let err = run(&global, json!([assignment])).await.unwrap_err();
+            assert!(format!("{:#}", err).contains(message), "{:#}", err);
+        }
+        assert_eq!(global.get("text").unwrap(), Some(json!("abc")));
+    }
 }
@@ -1,13 +1,21 @@
-use crate::nodes::NodeExecutor;
+use crate::nodes::{params_schema, parse_params, NodeExecutor};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
 use serde_json::Value;
 
 pub struct DelayExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DelayParams {
+    pub milliseconds: u64,
+}
+
 #[async_trait]
 impl NodeExecutor for DelayExecutor {
     async fn execute(
@@ -16,10 +24,8 @@ impl NodeExecutor for DelayExecutor {
         _global: &GlobalMemory,
         _nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
-        let ms = node.params
-            .get("milliseconds")
-            .and_then(|v| v.as_u64())
-            .context("Delay node requires 'milliseconds' parameter")?;
+        let params: DelayParams = parse_params(node)?;
+        let ms = params.milliseconds;
 
         log::info!("Delaying for {} ms", ms);
         tokio::time::sleep(tokio::time::Duration::from_millis(ms)).await;
@@ -29,10 +35,30 @@ impl NodeExecutor for DelayExecutor {
             output: Value::String(format!("Delayed for {} ms", ms)),
         })
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<DelayParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        parse_params::<DelayParams>(node).map(drop)
+    }
 }
 
 pub struct SwitchExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SwitchParams {
+    /// Expression such as `{{ global.count }} > 3` (templated)
+    pub condition: String,
+    /// Output `value` when the condition holds; strings are templated
+    #[serde(default)]
+    pub true_value: Option<Value>,
+    #[serde(default)]
+    pub false_value: Option<Value>,
+}
+
 #[async_trait]
 impl NodeExecutor for SwitchExecutor {
     async fn execute(
@@ -41,28 +67,21 @@ impl NodeExecutor for SwitchExecutor {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
+        let params: SwitchParams = parse_params(node)?;
         let template = TemplateEngine::new(global.clone(), nodes.clone());
-        
-        let condition = node.params
-            .get("condition")
-            .and_then(|v| v.as_str())
-            .context("Switch node requires 'condition' parameter")?;
 
-        let rendered_condition = template.render(condition)?;
+        let rendered_condition = template.render(&params.condition)?;
         
         log::info!("Evaluating condition: {}", rendered_condition);
 
         // Simple boolean evaluation
         let result = evaluate_condition(&rendered_condition)?;
         
-        let value = node.params.get(if result { "true_value" } else { "false_value" });
+        let value = if result { params.true_value } else { params.false_value };
         
         let output_value = match value {
-            Some(v) if v.is_string() => {
-                let s = v.as_str().unwrap();
-                Value::String(template.render(s)?)
-            }
-            Some(v) => v.clone(),
+            Some(Value::String(s)) => Value::String(template.render(&s)?),
+            Some(v) => v,
             None => Value::Bool(result),
         };
 
@@ -75,6 +94,14 @@ impl NodeExecutor for SwitchExecutor {
             }),
         })
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<SwitchParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        parse_params::<SwitchParams>(node).map(drop)
+    }
 }
 
 /// Simple condition evaluator supporting basic comparisons
@@ -134,3 +161,57 @@ fn parse_number(s: &str) -> Result<f64> {
     s.parse::<f64>()
         .with_context(|| format!("Cannot parse '{}' as number", s))
 }
+
+/// Collects the outputs of several nodes, e.g. the nodes expanded from a `matrix:`
+pub struct JoinExecutor;
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct JoinParams {
+    /// Node ids whose outputs are collected, in order
+    pub nodes: Vec<String>,
+    /// Matrix combination of each node, when generated from `matrix:`
+    #[serde(default)]
+    pub matrix: Option<Vec<Value>>,
+}
+
+#[async_trait]
+impl NodeExecutor for JoinExecutor {
+    async fn execute(
+        &self,
+        node: &Node,
+        _global: &GlobalMemory,
+        nodes: &NodeMemory,
+    ) -> Result<NodeOutput> {
+        let params: JoinParams = parse_params(node)?;
+        let labels = params.matrix.as_ref();
+
+        let mut items = Vec::with_capacity(params.nodes.len());
+        for (index, id) in params.nodes.iter().enumerate() {
+            let output = nodes.get(id)?
+                .with_context(|| format!("Join node: no output for node '{}'", id))?;
+
+            let mut item = serde_json::Map::new();
+            item.insert("id".to_string(), Value::String(id.clone()));
+            if let Some(label) = labels.and_then(|l| l.get(index)) {
+                item.insert("matrix".to_string(), label.clone());
+            }
+            item.insert("status".to_string(), Value::String(output.status));
+            item.insert("output".to_string(), output.output);
+            items.push(Value::Object(item));
+        }
+
+        Ok(NodeOutput {
+            status: "success".to_string(),
+            output: Value::Array(items),
+        })
+    }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<JoinParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        parse_params::<JoinParams>(node).map(drop)
+    }
+}
@@ -0,0 +1,213 @@
+//! Environments built from a script node's `requirements:`.
+//!
+//! Each distinct interpreter + requirements + mirror gets its own directory in
+//! the cache, named by a hash of the three. It is installed into a scratch
+//! directory and renamed into place, so other tasks and other workers sharing
+//! the cache either reuse a complete environment or build their own copy.
+
+use crate::nodes::runtime::{Harness, Runtime};
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+/// Package manager used to install requirements
+#[derive(Clone, Copy)]
+enum Installer {
+    Pip,
+    Npm,
+}
+
+impl Installer {
+    fn for_runtime(runtime: &Runtime) -> Option<Self> {
+        match runtime.harness {
+            Some(Harness::Python) => Some(Installer::Pip),
+            Some(Harness::Node) => Some(Installer::Npm),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Installer::Pip => "venv",
+            Installer::Npm => "node",
+        }
+    }
+}
+
+/// Whether `requirements:` can be installed for this runtime
+pub fn supports_requirements(runtime: &Runtime) -> bool {
+    Installer::for_runtime(runtime).is_some()
+}
+
+/// Point `runtime` at an environment with `requirements` installed, building it if needed
+pub async fn prepare(runtime: &mut Runtime, requirements: &[String], cache_dir: &Path) -> Result<()> {
+    let installer = Installer::for_runtime(runtime)
+        .context("'requirements' are only supported for Python and JavaScript runtimes")?;
+
+    let dir = cache_dir.join(format!("{}-{}", installer.name(), environment_hash(runtime, requirements)));
+    if !dir.exists() {
+        // One build per environment within this process; other processes race on the rename
+        static BUILDING: OnceLock<DashMap<PathBuf, Arc<Mutex<()>>>> = OnceLock::new();
+        let lock = BUILDING.get_or_init(DashMap::new).entry(dir.clone()).or_default().clone();
+        let _guard = lock.lock().await;
+        if !dir.exists() {
+            build(installer, runtime, requirements, cache_dir, &dir).await?;
+        }
+    }
+
+    match installer {
+        Installer::Pip => {
+            runtime.command = dir.join("bin").join("python").to_string_lossy().to_string();
+        }
+        Installer::Npm => {
+            runtime.env.push(("NODE_PATH".to_string(), dir.join("node_modules").to_string_lossy().to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn environment_hash(runtime: &Runtime, requirements: &[String]) -> String {
+    let mut sorted = requirements.to_vec();
+    sorted.sort();
+    sorted.dedup();
+
+    let mut hasher = Sha256::new();
+    hasher.update(runtime.command.as_bytes());
+    for requirement in &sorted {
+        hasher.update(b"\0");
+        hasher.update(requirement.as_bytes());
+    }
+    if let Some(mirror) = &runtime.mirror {
+        hasher.update(b"\0mirror\0");
+        hasher.update(mirror.to_string_lossy().as_bytes());
+    }
+    let digest = format!("{:x}", hasher.finalize());
+    digest[..16].to_string()
+}
+
+async fn build(installer: Installer, runtime: &Runtime, requirements: &[String], cache_dir: &Path, dir: &Path) -> Result<()> {
+    tokio::fs::create_dir_all(cache_dir).await
+        .with_context(|| format!("Failed to create environment cache `{:?}`", cache_dir))?;
+    let scratch = cache_dir.join(format!(".build-{}", uuid::Uuid::new_v4()));
+    log::info!("Building script environment {:?} for {}", dir, requirements.join(", "));
+
+    let built = install(installer, runtime, requirements, &scratch).await;
+    let built = match built {
+        Ok(()) => tokio::fs::rename(&scratch, dir).await.or_else(|e| {
+            // Another worker finished first; its environment is just as good
+            if dir.exists() { Ok(()) } else { Err(e) }
+        }).with_context(|| format!("Failed to move environment into `{:?}`", dir)),
+        Err(e) => Err(e),
+    };
+    if scratch.exists() {
+        let _ = tokio::fs::remove_dir_all(&scratch).await;
+    }
+    built
+}
+
+async fn install(installer: Installer, runtime: &Runtime, requirements: &[String], scratch: &Path) -> Result<()> {
+    match installer {
+        Installer::Pip => {
+            // Only `bin/python` is used, which keeps working after the venv is renamed
+            let mut venv = Command::new(&runtime.command);
+            venv.args(["-m", "venv"]).arg(scratch);
+            run(venv, "create virtualenv").await?;
+
+            let mut pip = Command::new(scratch.join("bin").join("python"));
+            pip.args(["-m", "pip", "install", "--quiet", "--disable-pip-version-check"]);
+            if let Some(mirror) = &runtime.mirror {
+                pip.arg("--no-index").arg("--find-links").arg(mirror);
+            }
+            pip.args(requirements);
+            run(pip, "install requirements").await
+        }
+        Installer::Npm => {
+            let mut npm = Command::new("npm");
+            npm.args(["install", "--no-audit", "--no-fund", "--no-save", "--prefix"]).arg(scratch);
+            if let Some(mirror) = &runtime.mirror {
+                npm.arg("--offline").arg("--cache").arg(mirror);
+            }
+            npm.args(requirements);
+            tokio::fs::create_dir_all(scratch).await?;
+            run(npm, "install requirements").await
+        }
+    }
+}
+
+async fn run(mut command: Command, action: &str) -> Result<()> {
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+    let output = command
+        .output()
+        .await
+        .with_context(|| format!("Failed to {}: could not run {}", action, program))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to {}: {}",
+            action,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nodes::runtime::RuntimeTable;
+
+    fn runtime(language: &str) -> Runtime {
+        RuntimeTable::builtin().resolve(language, None).unwrap()
+    }
+
+    fn requirements(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn hash_ignores_order_and_duplicates() {
+        let python = runtime("python");
+        let hash = environment_hash(&python, &requirements(&["requests", "pyyaml"]));
+        assert_eq!(hash, environment_hash(&python, &requirements(&["pyyaml", "requests", "pyyaml"])));
+        assert_ne!(hash, environment_hash(&python, &requirements(&["requests"])));
+
+        let mut mirrored = python.clone();
+        mirrored.mirror = Some(PathBuf::from("/srv/wheels"));
+        assert_ne!(hash, environment_hash(&mirrored, &requirements(&["requests", "pyyaml"])));
+
+        let mut other = python;
+        other.command = "python3.12".to_string();
+        assert_ne!(hash, environment_hash(&other, &requirements(&["requests", "pyyaml"])));
+    }
+
+    #[tokio::test]
+    async fn cached_environments_are_reused() {
+        let cache = std::env::temp_dir().join(format!("workflow-engine-envs-{}", uuid::Uuid::new_v4()));
+        let wanted = requirements(&["not-a-real-package==0.0.0"]);
+
+        // Pre-built environments; building them for real would fail
+        let mut python = runtime("python");
+        let venv = cache.join(format!("venv-{}", environment_hash(&python, &wanted)));
+        std::fs::create_dir_all(&venv).unwrap();
+        prepare(&mut python, &wanted, &cache).await.unwrap();
+        assert_eq!(python.command, venv.join("bin").join("python").to_string_lossy());
+
+        let mut node = runtime("javascript");
+        let modules = cache.join(format!("node-{}", environment_hash(&node, &wanted)));
+        std::fs::create_dir_all(&modules).unwrap();
+        prepare(&mut node, &wanted, &cache).await.unwrap();
+        assert_eq!(node.env, vec![("NODE_PATH".to_string(), modules.join("node_modules").to_string_lossy().to_string())]);
+        std::fs::remove_dir_all(&cache).ok();
+    }
+
+    #[tokio::test]
+    async fn only_python_and_javascript_take_requirements() {
+        let mut bash = runtime("bash");
+        assert!(!supports_requirements(&bash));
+        let err = prepare(&mut bash, &requirements(&["jq"]), &std::env::temp_dir()).await.unwrap_err();
+        assert!(err.to_string().contains("only supported for Python and JavaScript"), "{}", err);
+    }
+}
@@ -1,13 +1,46 @@
-use crate::nodes::NodeExecutor;
+use crate::nodes::{params_schema, parse_params, NodeExecutor};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
 use serde_json::Value;
 
 pub struct FileExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FileParams {
+    #[serde(default)]
+    pub operation: FileOperation,
+    /// File path (templated)
+    pub path: String,
+    /// Content for `write` / `append`; strings are templated, other values written as JSON
+    #[serde(default)]
+    pub content: Option<Value>,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileOperation {
+    #[default]
+    Read,
+    Write,
+    Append,
+}
+
+impl FileOperation {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FileOperation::Read => "read",
+            FileOperation::Write => "write",
+            FileOperation::Append => "append",
+        }
+    }
+}
+
 #[async_trait]
 impl NodeExecutor for FileExecutor {
     async fn execute(
@@ -16,24 +49,16 @@ impl NodeExecutor for FileExecutor {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
+        let params: FileParams = parse_params(node)?;
         let template = TemplateEngine::new(global.clone(), nodes.clone());
-        
-        let operation = node.params
-            .get("operation")
-            .and_then(|v| v.as_str())
-            .unwrap_or("read");
-
-        let path = node.params
-            .get("path")
-            .and_then(|v| v.as_str())
-            .context("File node requires 'path' parameter")?;
+        let operation = params.operation;
 
-        let rendered_path = template.render(path)?;
+        let rendered_path = template.render(&params.path)?;
 
-        log::info!("File operation: {} on {}", operation, rendered_path);
+        log::info!("File operation: {} on {}", operation.as_str(), rendered_path);
 
         match operation {
-            "read" => {
+            FileOperation::Read => {
                 let content = tokio::fs::read_to_string(&rendered_path)
                     .await
                     .context(format!("Failed to read file: {}", rendered_path))?;
@@ -46,9 +71,9 @@ impl NodeExecutor for FileExecutor {
                     }),
                 })
             }
-            "write" | "append" => {
-                let content = node.params
-                    .get("content")
+            FileOperation::Write | FileOperation::Append => {
+                let content = params.content
+                    .as_ref()
                     .context("File write/append requires 'content' parameter")?;
 
                 let content_str = match content {
@@ -56,7 +81,7 @@ impl NodeExecutor for FileExecutor {
                     _ => content.to_string(),
                 };
 
-                if operation == "write" {
+                if operation == FileOperation::Write {
                     tokio::fs::write(&rendered_path, content_str.as_bytes())
                         .await
                         .context(format!("Failed to write file: {}", rendered_path))?;
@@ -78,12 +103,19 @@ impl NodeExecutor for FileExecutor {
                     status: "success".to_string(),
                     output: serde_json::json!({
                         "path": rendered_path,
-                        "operation": operation,
+                        "operation": operation.as_str(),
                         "bytes_written": content_str.len()
                     }),
                 })
             }
-            _ => anyhow::bail!("Unsupported file operation: {}", operation),
         }
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<FileParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        parse_params::<FileParams>(node).map(drop)
+    }
 }
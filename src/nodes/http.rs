@@ -1,13 +1,589 @@
-use crate::nodes::NodeExecutor;
+use crate::http_pool::{HttpPool, PooledResponse};
+use crate::nodes::pagination::{NextPage, Pagination};
+use crate::nodes::{params_schema, parse_params, NodeExecutor};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
-use anyhow::{Result, Context};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::{multipart, redirect, Certificate, Client, Identity, Method, RequestBuilder};
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
 use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 
 pub struct HttpExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HttpParams {
+    /// Request URL (templated)
+    pub url: String,
+    /// Any HTTP method (`GET`, `POST`, `PUT`, `PATCH`, `DELETE`, `HEAD`, ...)
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// Request headers (values templated)
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Query parameters appended to the URL (templated); arrays repeat the key
+    #[serde(default)]
+    pub query: BTreeMap<String, Value>,
+    /// Request body (templated); how it is sent depends on `body_type`
+    #[serde(default)]
+    pub body: Option<Value>,
+    /// `json` by default, or `multipart` when `files` are given
+    #[serde(default)]
+    pub body_type: Option<BodyType>,
+    /// `multipart` only: form field -> path of a file to upload (templated)
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+    #[serde(default)]
+    pub auth: Option<Auth>,
+    /// Whole-request timeout, in milliseconds
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub connect_timeout_ms: Option<u64>,
+    /// Follow up to this many redirects; 0 disables following them
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: usize,
+    #[serde(default)]
+    pub tls: TlsOptions,
+    /// Statuses that count as success: codes or patterns like `2xx` / `200-299`; `2xx` by default
+    #[serde(default)]
+    pub expected_status: Vec<StatusPattern>,
+    /// Fail the node (stopping the workflow) on any other status, instead of
+    /// recording `status: failed`
+    #[serde(default)]
+    pub fail_on_status: bool,
+    /// Write the response body to this file under the working directory (templated)
+    /// instead of `output.body`; use it for binary responses. Only an expected
+    /// status is saved; any other body goes to `output.body`
+    #[serde(default)]
+    pub save_to: Option<String>,
+    /// Request page after page and collect the items into `output.items`
+    #[serde(default)]
+    pub pagination: Option<Pagination>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_max_redirects() -> usize {
+    10
+}
+
+/// An expected status code, or a class (`2xx`) or range (`200-299`) of them
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(untagged)]
+pub enum StatusPattern {
+    Code(u16),
+    Pattern(String),
+}
+
+impl StatusPattern {
+    fn range(&self) -> Result<(u16, u16)> {
+        let invalid = || anyhow::anyhow!("Invalid expected_status '{}'", self);
+        match self {
+            StatusPattern::Code(code) => Ok((*code, *code)),
+            StatusPattern::Pattern(pattern) => {
+                let pattern = pattern.trim().to_ascii_lowercase();
+                if let Some(class) = pattern.strip_suffix("xx") {
+                    let class: u16 = class.parse().ok().filter(|c| (1..=5).contains(c)).ok_or_else(invalid)?;
+                    return Ok((class * 100, class * 100 + 99));
+                }
+                match pattern.split_once('-') {
+                    Some((from, to)) => {
+                        let from: u16 = from.trim().parse().map_err(|_| invalid())?;
+                        let to: u16 = to.trim().parse().map_err(|_| invalid())?;
+                        if from > to {
+                            return Err(invalid());
+                        }
+                        Ok((from, to))
+                    }
+                    None => pattern.parse().map(|code| (code, code)).map_err(|_| invalid()),
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for StatusPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatusPattern::Code(code) => write!(f, "{}", code),
+            StatusPattern::Pattern(pattern) => f.write_str(pattern),
+        }
+    }
+}
+
+fn status_expected(status: u16, expected: &[StatusPattern]) -> Result<bool> {
+    if expected.is_empty() {
+        return Ok((200..300).contains(&status));
+    }
+    for pattern in expected {
+        let (from, to) = pattern.range()?;
+        if (from..=to).contains(&status) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Response headers by lowercase name; repeated headers are joined with `, `
+fn header_map(headers: &reqwest::header::HeaderMap) -> Value {
+    let mut map = serde_json::Map::new();
+    for (name, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+        match map.get_mut(name.as_str()) {
+            Some(Value::String(existing)) => {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            }
+            _ => {
+                map.insert(name.as_str().to_string(), Value::String(value));
+            }
+        }
+    }
+    Value::Object(map)
+}
+
+fn is_json(content_type: Option<&str>) -> bool {
+    content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|mime| {
+            let mime = mime.trim().to_ascii_lowercase();
+            mime == "application/json" || mime.ends_with("+json")
+        })
+        .unwrap_or(false)
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyType {
+    /// `body` as JSON
+    Json,
+    /// `body` (an object) as `application/x-www-form-urlencoded`
+    Form,
+    /// `body` (an object) as form fields plus the `files`, as `multipart/form-data`
+    Multipart,
+    /// `body` (a string) as-is, `text/plain` unless a `Content-Type` header is set
+    Text,
+    /// The contents of the file at `body` (a path)
+    Binary,
+}
+
+/// Only environment variables with this prefix can be used as secrets
+const SECRET_ENV_PREFIX: &str = "WORKFLOW_SECRET_";
+
+/// A credential: a literal (templated) or the name of an environment variable
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Secret {
+    Env {
+        /// Environment variable holding the value, named `WORKFLOW_SECRET_*`
+        /// (`.env` is loaded at startup)
+        env: String,
+    },
+    Value(String),
+}
+
+impl Secret {
+    fn resolve(&self, template: &TemplateEngine) -> Result<String> {
+        self.check()?;
+        match self {
+            Secret::Env { env } => std::env::var(env)
+                .with_context(|| format!("Environment variable {} is not set", env)),
+            Secret::Value(value) => template.render(value),
+        }
+    }
+
+    /// Keep workflows from reading arbitrary host environment variables
+    fn check(&self) -> Result<()> {
+        match self {
+            Secret::Env { env } if !env.starts_with(SECRET_ENV_PREFIX) => anyhow::bail!(
+                "Secret environment variable '{}' must start with {}",
+                env,
+                SECRET_ENV_PREFIX
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Auth {
+    fn secrets(&self) -> Vec<&Secret> {
+        match self {
+            Auth::Bearer { token } => vec![token],
+            Auth::Basic { username, password } => std::iter::once(username).chain(password.as_ref()).collect(),
+            Auth::ApiKey { key, .. } => vec![key],
+        }
+    }
+}
+
+/// `path` (relative to the working directory) if it is a file under the working
+/// directory, so a workflow can't upload arbitrary host files
+async fn upload_path(path: &str) -> Result<PathBuf> {
+    let root = tokio::fs::canonicalize(std::env::current_dir()?).await?;
+    let full = tokio::fs::canonicalize(root.join(path))
+        .await
+        .with_context(|| format!("Could not find upload file `{}`", path))?;
+    if !full.starts_with(&root) {
+        anyhow::bail!("Upload file `{}` is outside the working directory {}", path, root.display());
+    }
+    Ok(full)
+}
+
+/// Where to write `save_to`: like [`upload_path`], it must be under the working
+/// directory, but the file (and its directories) may not exist yet
+async fn save_path(path: &str) -> Result<PathBuf> {
+    let root = tokio::fs::canonicalize(std::env::current_dir()?).await?;
+    let full = root.join(path);
+    let mut existing = full.as_path();
+    while !tokio::fs::try_exists(existing).await.unwrap_or(false) {
+        existing = existing.parent().context("Working directory does not exist")?;
+    }
+    let missing = full.strip_prefix(existing)?;
+    let resolved = tokio::fs::canonicalize(existing).await?.join(missing);
+    let escapes = missing.components().any(|c| c == std::path::Component::ParentDir);
+    if escapes || !resolved.starts_with(&root) {
+        anyhow::bail!("save_to file `{}` is outside the working directory {}", path, root.display());
+    }
+    Ok(resolved)
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Auth {
+    /// `Authorization: Bearer <token>`
+    Bearer { token: Secret },
+    Basic {
+        username: Secret,
+        #[serde(default)]
+        password: Option<Secret>,
+    },
+    /// A key sent in a header (`X-API-Key` by default) or a query parameter
+    ApiKey {
+        key: Secret,
+        #[serde(default)]
+        header: Option<String>,
+        #[serde(default)]
+        query: Option<String>,
+    },
+}
+
+#[derive(Deserialize, JsonSchema, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TlsOptions {
+    /// Accept invalid or self-signed server certificates
+    #[serde(default)]
+    pub insecure: bool,
+    /// Extra CA certificate to trust, a PEM file
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+    /// Client certificate and its PKCS#8 key, PEM files, for mutual TLS
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    #[serde(default)]
+    pub client_key: Option<String>,
+}
+
+impl HttpParams {
+    fn body_type(&self) -> BodyType {
+        match self.body_type {
+            Some(body_type) => body_type,
+            None if !self.files.is_empty() => BodyType::Multipart,
+            None => BodyType::Json,
+        }
+    }
+
+    /// The shared client, unless redirect, connect timeout or TLS settings call for a dedicated one
+    fn client(&self, pool: &HttpPool) -> Result<Client> {
+        let tls = &self.tls;
+        let custom = self.max_redirects != default_max_redirects()
+            || self.connect_timeout_ms.is_some()
+            || tls.insecure
+            || tls.ca_cert.is_some()
+            || tls.client_cert.is_some();
+        if !custom {
+            return Ok(pool.client().clone());
+        }
+        let mut builder = Client::builder().redirect(match self.max_redirects {
+            0 => redirect::Policy::none(),
+            n => redirect::Policy::limited(n),
+        });
+        if let Some(ms) = self.connect_timeout_ms {
+            builder = builder.connect_timeout(Duration::from_millis(ms));
+        }
+        if self.tls.insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        if let Some(path) = &self.tls.ca_cert {
+            let pem = std::fs::read(path).with_context(|| format!("Could not read CA certificate `{}`", path))?;
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+        if let (Some(cert), Some(key)) = (&self.tls.client_cert, &self.tls.client_key) {
+            let cert = std::fs::read(cert).with_context(|| format!("Could not read client certificate `{}`", cert))?;
+            let key = std::fs::read(key).with_context(|| format!("Could not read client key `{}`", key))?;
+            builder = builder.identity(Identity::from_pkcs8_pem(&cert, &key)?);
+        }
+        builder.build().context("Failed to build HTTP client")
+    }
+}
+
+/// Query and form values are sent as text; arrays repeat their key
+fn form_pairs(name: &str, value: &Value, pairs: &mut Vec<(String, String)>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| form_pairs(name, item, pairs)),
+        Value::Null => {}
+        Value::String(s) => pairs.push((name.to_string(), s.clone())),
+        other => pairs.push((name.to_string(), other.to_string())),
+    }
+}
+
+fn object_pairs(body: &Value, body_type: &str) -> Result<Vec<(String, String)>> {
+    let object = body
+        .as_object()
+        .with_context(|| format!("HTTP body_type '{}' requires 'body' to be an object", body_type))?;
+    let mut pairs = Vec::new();
+    for (name, value) in object {
+        form_pairs(name, value, &mut pairs);
+    }
+    Ok(pairs)
+}
+
+async fn with_body(
+    request: RequestBuilder,
+    params: &HttpParams,
+    body: Option<Value>,
+    template: &TemplateEngine,
+) -> Result<RequestBuilder> {
+    let body_type = params.body_type();
+    let body = match (body, body_type) {
+        (None, BodyType::Multipart) => Value::Object(Default::default()),
+        (None, _) => return Ok(request),
+        (Some(body), _) => body,
+    };
+
+    Ok(match body_type {
+        BodyType::Json => request.json(&body),
+        BodyType::Form => request.form(&object_pairs(&body, "form")?),
+        BodyType::Text => {
+            let text = match body {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            let request = match params.headers.keys().any(|k| k.eq_ignore_ascii_case("content-type")) {
+                true => request,
+                false => request.header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            };
+            request.body(text)
+        }
+        BodyType::Binary => {
+            let path = body.as_str().context("HTTP body_type 'binary' requires 'body' to be a file path")?;
+            let bytes = tokio::fs::read(upload_path(path).await?).await
+                .with_context(|| format!("Could not read request body file `{}`", path))?;
+            request.body(bytes)
+        }
+        BodyType::Multipart => {
+            let mut form = multipart::Form::new();
+            for (name, value) in object_pairs(&body, "multipart")? {
+                form = form.text(name, value);
+            }
+            for (field, path) in &params.files {
+                let path = template.render(path)?;
+                let bytes = tokio::fs::read(upload_path(&path).await?).await
+                    .with_context(|| format!("Could not read upload file `{}`", path))?;
+                let file_name = Path::new(&path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let mime = mime_guess::from_path(&path).first_or_octet_stream();
+                let part = multipart::Part::bytes(bytes).file_name(file_name).mime_str(mime.as_ref())?;
+                form = form.part(field.clone(), part);
+            }
+            request.multipart(form)
+        }
+    })
+}
+
+/// Resolved `auth` credentials
+enum Credentials {
+    Bearer(String),
+    Basic(String, Option<String>),
+    Header(String, String),
+    Query(String, String),
+}
+
+/// A request with its templates rendered, ready to be sent (once per page when paginating)
+struct Prepared<'a> {
+    params: &'a HttpParams,
+    pool: HttpPool,
+    client: Client,
+    method: Method,
+    url: String,
+    headers: Vec<(String, String)>,
+    query: Vec<(String, String)>,
+    credentials: Option<Credentials>,
+    body: Option<Value>,
+}
+
+impl<'a> Prepared<'a> {
+    fn new(params: &'a HttpParams, pool: &HttpPool, template: &TemplateEngine) -> Result<Self> {
+        let method = Method::from_bytes(params.method.to_uppercase().as_bytes())
+            .with_context(|| format!("Unsupported HTTP method: {}", params.method))?;
+
+        let mut query = Vec::new();
+        for (name, value) in &params.query {
+            form_pairs(name, &template.render_value(value)?, &mut query);
+        }
+        let headers = params
+            .headers
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), template.render(value)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let credentials = match &params.auth {
+            None => None,
+            Some(Auth::Bearer { token }) => Some(Credentials::Bearer(token.resolve(template)?)),
+            Some(Auth::Basic { username, password }) => Some(Credentials::Basic(
+                username.resolve(template)?,
+                password.as_ref().map(|p| p.resolve(template)).transpose()?,
+            )),
+            Some(Auth::ApiKey { key, header, query }) => {
+                let key = key.resolve(template)?;
+                Some(match query {
+                    Some(name) => Credentials::Query(name.clone(), key),
+                    None => Credentials::Header(header.clone().unwrap_or_else(|| "X-API-Key".to_string()), key),
+                })
+            }
+        };
+
+        Ok(Self {
+            params,
+            pool: pool.clone(),
+            client: params.client(pool)?,
+            method,
+            url: template.render(&params.url)?,
+            headers,
+            query,
+            credentials,
+            body: params.body.as_ref().map(|b| template.render_value(b)).transpose()?,
+        })
+    }
+
+    async fn send(&self, url: &str, query: &[(String, String)], template: &TemplateEngine) -> Result<PooledResponse> {
+        log::info!("HTTP {} request to: {}", self.method, url);
+
+        let mut request = self.client.request(self.method.clone(), url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let mut query = query.to_vec();
+        match &self.credentials {
+            None => {}
+            Some(Credentials::Bearer(token)) => request = request.bearer_auth(token),
+            Some(Credentials::Basic(username, password)) => request = request.basic_auth(username, password.as_ref()),
+            Some(Credentials::Header(name, key)) => request = request.header(name, key),
+            Some(Credentials::Query(name, key)) => query.push((name.clone(), key.clone())),
+        }
+        if !query.is_empty() {
+            request = request.query(&query);
+        }
+        if let Some(ms) = self.params.timeout_ms {
+            request = request.timeout(Duration::from_millis(ms));
+        }
+        let request = with_body(request, self.params, self.body.clone(), template).await?;
+        self.pool.send(request).await
+    }
+
+    /// `fail_on_status` error for an unexpected response
+    fn status_error(&self, url: &str, status: u16, body: &[u8]) -> anyhow::Error {
+        let excerpt: String = String::from_utf8_lossy(body).chars().take(500).collect();
+        anyhow::anyhow!("HTTP {} {} returned status {}: {}", self.method, url, status, excerpt)
+    }
+}
+
+/// `base` with the parameters named in `overrides` replaced
+fn merge_query(base: &[(String, String)], overrides: &[(String, String)]) -> Vec<(String, String)> {
+    base.iter()
+        .filter(|(name, _)| !overrides.iter().any(|(o, _)| o == name))
+        .chain(overrides)
+        .cloned()
+        .collect()
+}
+
+/// Request pages until the listing ends or `max_pages` is reached
+async fn paginate(prepared: &Prepared<'_>, pagination: &Pagination, template: &TemplateEngine) -> Result<NodeOutput> {
+    let params = prepared.params;
+    let mut url = prepared.url.clone();
+    let mut query = merge_query(&prepared.query, &pagination.first_query());
+    let mut items = Vec::new();
+    let mut pages: u64 = 0;
+    let mut has_more = false;
+    // Pages already requested, so a server repeating a cursor or link can't loop forever
+    let mut seen = HashSet::new();
+
+    let (status, headers, success) = loop {
+        let response = prepared.send(&url, &query, template).await?;
+        let status = response.status().as_u16();
+        let headers = header_map(response.headers());
+        let bytes = response.bytes().await?;
+        let success = status_expected(status, &params.expected_status)?;
+        pages += 1;
+
+        if !success {
+            if params.fail_on_status {
+                return Err(prepared.status_error(&url, status, &bytes));
+            }
+            break (status, headers, success);
+        }
+
+        let body: Value = serde_json::from_slice(&bytes)
+            .with_context(|| format!("Page {} from {} is not valid JSON", pages, url))?;
+        let page_items = pagination.items(&body)?;
+        let count = page_items.len();
+        items.extend(page_items);
+
+        let link = headers.get("link").and_then(Value::as_str);
+        match pagination.next(pages - 1, &url, link, &body, count)? {
+            None => break (status, headers, success),
+            Some(next) if !seen.insert(next.clone()) => {
+                log::warn!("Page {} from {} points back to a page already fetched; stopping", pages, url);
+                break (status, headers, success);
+            }
+            Some(_) if pages as usize >= pagination.max_pages => {
+                has_more = true;
+                break (status, headers, success);
+            }
+            Some(NextPage::Url(next)) => {
+                // Next links carry their own query string
+                url = next;
+                query = Vec::new();
+            }
+            Some(NextPage::Query(next)) => query = merge_query(&prepared.query, &next),
+        }
+    };
+
+    log::info!("Fetched {} items in {} pages", items.len(), pages);
+
+    Ok(NodeOutput {
+        status: if success { "success".to_string() } else { "failed".to_string() },
+        output: serde_json::json!({
+            "status": status,
+            "headers": headers,
+            "success": success,
+            "items": items,
+            "pages": pages,
+            "has_more": has_more
+        }),
+    })
+}
+
 #[async_trait]
 impl NodeExecutor for HttpExecutor {
     async fn execute(
@@ -16,44 +592,208 @@ impl NodeExecutor for HttpExecutor {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
+        let params: HttpParams = parse_params(node)?;
         let template = TemplateEngine::new(global.clone(), nodes.clone());
-        
-        let url = node.params
-            .get("url")
-            .and_then(|v| v.as_str())
-            .context("HTTP node requires 'url' parameter")?;
-
-        let method = node.params
-            .get("method")
-            .and_then(|v| v.as_str())
-            .unwrap_or("GET");
-
-        let rendered_url = template.render(url)?;
-        
-        log::info!("HTTP {} request to: {}", method, rendered_url);
-
-        let client = reqwest::Client::new();
-        let response = match method.to_uppercase().as_str() {
-            "GET" => client.get(&rendered_url).send().await?,
-            "POST" => {
-                let body = node.params.get("body").unwrap_or(&Value::Null);
-                client.post(&rendered_url).json(&body).send().await?
-            }
-            _ => anyhow::bail!("Unsupported HTTP method: {}", method),
-        };
+        let prepared = Prepared::new(&params, global.http(), &template)?;
 
+        if let Some(pagination) = &params.pagination {
+            return paginate(&prepared, pagination, &template).await;
+        }
+
+        let url = &prepared.url;
+        let response = prepared.send(url, &prepared.query, &template).await?;
         let status = response.status().as_u16();
-        let body = response.text().await?;
+        let headers = header_map(response.headers());
+        let content_type = headers.get("content-type").and_then(Value::as_str).map(str::to_string);
+        let success = status_expected(status, &params.expected_status)?;
+
+        if !success && params.fail_on_status {
+            let bytes = response.bytes().await?;
+            return Err(prepared.status_error(url, status, &bytes));
+        }
 
-        let result = serde_json::json!({
+        let mut result = serde_json::json!({
             "status": status,
-            "body": body,
-            "success": status >= 200 && status < 300
+            "headers": headers,
+            "success": success
         });
+        // Only a wanted response is saved; an error body is kept for inspection
+        match params.save_to.as_ref().filter(|_| success) {
+            Some(path) => {
+                let path = template.render(path)?;
+                let target = save_path(&path).await?;
+                if let Some(dir) = target.parent() {
+                    tokio::fs::create_dir_all(dir).await?;
+                }
+                // Stream to disk so large downloads are never held in memory
+                let mut file = tokio::fs::File::create(&target).await
+                    .with_context(|| format!("Failed to save HTTP response to `{}`", path))?;
+                let mut body = std::pin::pin!(response.bytes_stream());
+                let mut size = 0;
+                while let Some(chunk) = body.next().await {
+                    let chunk = chunk.with_context(|| format!("Failed to download HTTP response from {}", url))?;
+                    file.write_all(&chunk).await
+                        .with_context(|| format!("Failed to save HTTP response to `{}`", path))?;
+                    size += chunk.len();
+                }
+                file.flush().await?;
+                result["file"] = Value::String(path);
+                result["size"] = Value::from(size);
+            }
+            None => {
+                let bytes = response.bytes().await?;
+                let body = String::from_utf8_lossy(&bytes).to_string();
+                if is_json(content_type.as_deref()) && !bytes.is_empty() {
+                    match serde_json::from_slice::<Value>(&bytes) {
+                        Ok(json) => result["json"] = json,
+                        Err(e) => log::warn!("HTTP response from {} is not valid JSON: {}", url, e),
+                    }
+                }
+                result["body"] = Value::String(body);
+            }
+        }
 
         Ok(NodeOutput {
-            status: "success".to_string(),
+            status: if success { "success".to_string() } else { "failed".to_string() },
             output: result,
         })
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<HttpParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        let params: HttpParams = parse_params(node)?;
+        if Method::from_bytes(params.method.to_uppercase().as_bytes()).is_err() {
+            anyhow::bail!("HTTP node '{}': unsupported method '{}'", node.id, params.method);
+        }
+        if !params.files.is_empty() && params.body_type() != BodyType::Multipart {
+            anyhow::bail!("HTTP node '{}': 'files' require body_type 'multipart'", node.id);
+        }
+        for pattern in &params.expected_status {
+            pattern.range().with_context(|| format!("HTTP node '{}'", node.id))?;
+        }
+        if let Some(pagination) = &params.pagination {
+            pagination.validate().with_context(|| format!("HTTP node '{}'", node.id))?;
+            if params.save_to.is_some() {
+                anyhow::bail!("HTTP node '{}': 'save_to' cannot be combined with 'pagination'", node.id);
+            }
+        }
+        for secret in params.auth.iter().flat_map(Auth::secrets) {
+            secret.check().with_context(|| format!("HTTP node '{}'", node.id))?;
+        }
+        if params.tls.client_cert.is_some() != params.tls.client_key.is_some() {
+            anyhow::bail!("HTTP node '{}': 'tls.client_cert' and 'tls.client_key' go together", node.id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn validate(params: Value) -> Result<()> {
+        let node = serde_json::from_value(json!({"id": "h", "type": "http", "params": params})).unwrap();
+        HttpExecutor.validate(&node)
+    }
+
+    #[test]
+    fn secrets_are_limited_to_the_secret_prefix() {
+        let err = validate(json!({"url": "http://x", "auth": {"type": "bearer", "token": {"env": "HOME"}}})).unwrap_err();
+        assert!(format!("{:#}", err).contains("'HOME' must start with WORKFLOW_SECRET_"), "{:#}", err);
+        validate(json!({"url": "http://x", "auth": {"type": "bearer", "token": {"env": "WORKFLOW_SECRET_TOKEN"}}})).unwrap();
+        validate(json!({"url": "http://x", "auth": {"type": "basic", "username": "u", "password": "{{ global.pw }}"}})).unwrap();
+
+        let template = TemplateEngine::new(GlobalMemory::new(), NodeMemory::new());
+        let err = Secret::Env { env: "PATH".to_string() }.resolve(&template).unwrap_err();
+        assert!(err.to_string().contains("must start with"));
+    }
+
+    #[tokio::test]
+    async fn uploads_must_stay_under_the_working_directory() {
+        let cwd = std::env::current_dir().unwrap().canonicalize().unwrap();
+        assert_eq!(upload_path("Cargo.toml").await.unwrap(), cwd.join("Cargo.toml"));
+        assert_eq!(upload_path("src/../Cargo.toml").await.unwrap(), cwd.join("Cargo.toml"));
+
+        let outside = std::env::temp_dir().join(format!("workflow-engine-upload-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&outside, "secret").unwrap();
+        let err = upload_path(&outside.to_string_lossy()).await.unwrap_err();
+        assert!(err.to_string().contains("outside the working directory"), "{}", err);
+        std::fs::remove_file(&outside).ok();
+        assert!(upload_path("../Cargo.toml").await.is_err());
+        assert!(upload_path("no-such-file").await.is_err());
+    }
+
+    fn pattern(text: &str) -> StatusPattern {
+        StatusPattern::Pattern(text.to_string())
+    }
+
+    #[test]
+    fn status_pattern_range() {
+        assert_eq!(StatusPattern::Code(404).range().unwrap(), (404, 404));
+        assert_eq!(pattern("2xx").range().unwrap(), (200, 299));
+        assert_eq!(pattern(" 4XX ").range().unwrap(), (400, 499));
+        assert_eq!(pattern("1xx").range().unwrap(), (100, 199));
+        assert_eq!(pattern("200-204").range().unwrap(), (200, 204));
+        assert_eq!(pattern("301").range().unwrap(), (301, 301));
+        for invalid in ["xx", "abc", "2-x", "ok", "0xx", "6xx", "700xx", "299-200"] {
+            assert!(pattern(invalid).range().is_err(), "{}", invalid);
+        }
+        assert!(validate(json!({"url": "http://x", "expected_status": ["700xx"]})).is_err());
+    }
+
+    #[test]
+    fn status_expected_defaults_to_2xx() {
+        assert!(status_expected(204, &[]).unwrap());
+        assert!(!status_expected(304, &[]).unwrap());
+        let expected = [StatusPattern::Code(404), pattern("2xx")];
+        assert!(status_expected(404, &expected).unwrap());
+        assert!(!status_expected(500, &expected).unwrap());
+    }
+
+    #[tokio::test]
+    async fn save_to_must_stay_under_the_working_directory() {
+        let cwd = std::env::current_dir().unwrap().canonicalize().unwrap();
+        assert_eq!(save_path("target/new/dir/out.bin").await.unwrap(), cwd.join("target/new/dir/out.bin"));
+        assert_eq!(save_path("src/../out.bin").await.unwrap(), cwd.join("out.bin"));
+        for outside in ["../out.bin", "/tmp/out.bin", "target/new/../../../out.bin"] {
+            let err = save_path(outside).await.unwrap_err();
+            assert!(err.to_string().contains("outside the working directory"), "{}: {}", outside, err);
+        }
+    }
+
+    #[tokio::test]
+    async fn save_to_writes_only_expected_responses() {
+        use axum::{http::StatusCode, routing::get, Router};
+
+        let app = Router::new()
+            .route("/ok", get(|| async { "payload" }))
+            .route("/missing", get(|| async { (StatusCode::NOT_FOUND, "no such thing") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let dir = format!("target/workflow-engine-save-{}", uuid::Uuid::new_v4());
+        let fetch = |route: &str| {
+            let node: Node = serde_json::from_value(json!({"id": "h", "type": "http", "params": {
+                "url": format!("http://{}/{}", addr, route),
+                "save_to": format!("{}/{}.txt", dir, route),
+            }}))
+            .unwrap();
+            async move { HttpExecutor.execute(&node, &GlobalMemory::new(), &NodeMemory::new()).await.unwrap() }
+        };
+
+        let saved = fetch("ok").await;
+        assert_eq!(saved.output["size"], json!(7));
+        assert_eq!(std::fs::read_to_string(format!("{}/ok.txt", dir)).unwrap(), "payload");
+
+        let missing = fetch("missing").await;
+        assert_eq!(missing.status, "failed");
+        assert_eq!(missing.output["body"], json!("no such thing"));
+        assert!(!Path::new(&format!("{}/missing.txt", dir)).exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -1,13 +1,30 @@
-use crate::nodes::NodeExecutor;
+use crate::nodes::{params_schema, parse_params, NodeExecutor};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
 use serde_json::Value;
 
 pub struct InputExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct InputParams {
+    /// Prompt shown on the terminal (templated)
+    #[serde(default = "default_prompt")]
+    pub prompt: String,
+    /// Used when the answer is empty
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+fn default_prompt() -> String {
+    "Please enter value:".to_string()
+}
+
 #[async_trait]
 impl NodeExecutor for InputExecutor {
     async fn execute(
@@ -16,19 +33,11 @@ impl NodeExecutor for InputExecutor {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
+        let params: InputParams = parse_params(node)?;
         let template = TemplateEngine::new(global.clone(), nodes.clone());
-        
-        let prompt = node.params
-            .get("prompt")
-            .and_then(|v| v.as_str())
-            .unwrap_or("Please enter value:");
             
-        let rendered_prompt = template.render(prompt)?;
-        
-        let default = node.params
-            .get("default")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string());
+        let rendered_prompt = template.render(&params.prompt)?;
+        let default = params.default;
 
         // Use tokio's blocking task for stdin interaction to avoid blocking the runtime
         let result = tokio::task::spawn_blocking(move || {
@@ -58,4 +67,12 @@ impl NodeExecutor for InputExecutor {
             output: Value::String(result),
         })
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<InputParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        parse_params::<InputParams>(node).map(drop)
+    }
 }
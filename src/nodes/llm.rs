@@ -1,13 +1,95 @@
-use crate::nodes::NodeExecutor;
+use crate::nodes::{params_schema, parse_params, NodeExecutor};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
 use serde_json::Value;
 
 pub struct LlmExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LlmParams {
+    /// User prompt (templated)
+    pub prompt: String,
+    /// System prompt (templated)
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default = "default_model")]
+    pub model: String,
+    #[serde(default = "default_temperature")]
+    pub temperature: f64,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Used when `OPENAI_API_KEY` is not set
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Defaults to `OPENAI_BASE_URL` or the OpenAI API
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// `json` asks for a JSON object, `json_schema` for one matching `schema`;
+    /// either way the reply is parsed into `output.json`
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+    /// JSON Schema the reply must match; sent to the API with `json_schema`,
+    /// checked locally in any case
+    #[serde(default)]
+    pub schema: Option<Value>,
+    /// Ask the API to enforce `schema` exactly (`json_schema` only). Strict
+    /// schemas need `additionalProperties: false` and every property in `required`
+    #[serde(default = "default_strict")]
+    pub strict: bool,
+    /// Times to ask the model to fix a reply that is not valid JSON or does not match `schema`
+    #[serde(default)]
+    pub repair_attempts: u32,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    /// OpenAI JSON mode
+    #[serde(alias = "json_object")]
+    Json,
+    /// OpenAI structured outputs
+    JsonSchema,
+}
+
+impl LlmParams {
+    /// `schema` alone implies `json_schema`
+    fn response_format(&self) -> ResponseFormat {
+        match (self.response_format, &self.schema) {
+            (Some(format), _) => format,
+            (None, Some(_)) => ResponseFormat::JsonSchema,
+            (None, None) => ResponseFormat::Text,
+        }
+    }
+
+    fn validator(&self) -> Result<Option<jsonschema::Validator>> {
+        self.schema
+            .as_ref()
+            .map(jsonschema::validator_for)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .context("Invalid 'schema'")
+    }
+}
+
+fn default_model() -> String {
+    "gpt-3.5-turbo".to_string()
+}
+
+fn default_temperature() -> f64 {
+    0.7
+}
+
+fn default_strict() -> bool {
+    true
+}
+
 #[async_trait]
 impl NodeExecutor for LlmExecutor {
     async fn execute(
@@ -16,52 +98,31 @@ impl NodeExecutor for LlmExecutor {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
+        let params: LlmParams = parse_params(node)?;
         let template = TemplateEngine::new(global.clone(), nodes.clone());
         
         // Get API key from environment or params
         let api_key = std::env::var("OPENAI_API_KEY")
-            .or_else(|_| {
-                node.params
-                    .get("api_key")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string())
-                    .ok_or_else(|| anyhow::anyhow!("OPENAI_API_KEY not found in environment or params"))
-            })?;
-
-        let base_url = node.params
-            .get("base_url")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
+            .ok()
+            .or_else(|| params.api_key.clone())
+            .context("OPENAI_API_KEY not found in environment or params")?;
+
+        let base_url = params.base_url
+            .clone()
             .or_else(|| std::env::var("OPENAI_BASE_URL").ok())
             .unwrap_or_else(|| "https://api.openai.com/v1".to_string());
 
-        let model = node.params
-            .get("model")
-            .and_then(|v| v.as_str())
-            .unwrap_or("gpt-3.5-turbo");
+        let model = params.model.as_str();
 
-        let system = node.params
-            .get("system")
-            .and_then(|v| v.as_str())
+        let system = params.system
+            .as_deref()
             .map(|s| template.render(s))
             .transpose()?;
 
-        let prompt = node.params
-            .get("prompt")
-            .and_then(|v| v.as_str())
-            .context("LLM node requires 'prompt' parameter")?;
-        
-        let rendered_prompt = template.render(prompt)?;
-
-        let temperature = node.params
-            .get("temperature")
-            .and_then(|v| v.as_f64())
-            .unwrap_or(0.7);
+        let rendered_prompt = template.render(&params.prompt)?;
 
-        let max_tokens = node.params
-            .get("max_tokens")
-            .and_then(|v| v.as_u64())
-            .map(|v| v as i32);
+        let temperature = params.temperature;
+        let max_tokens = params.max_tokens;
 
         log::info!("Calling LLM: {} (model: {})", node.name, model);
 
@@ -89,43 +150,249 @@ impl NodeExecutor for LlmExecutor {
             request_body["max_tokens"] = serde_json::json!(tokens);
         }
 
-        // Call OpenAI API
-        let client = reqwest::Client::new();
-        let response = client
-            .post(format!("{}/chat/completions", base_url))
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await
-            .context("Failed to call LLM API")?;
-
-        let status = response.status();
-        let response_text = response.text().await?;
-
-        if !status.is_success() {
-            anyhow::bail!("LLM API error ({}): {}", status, response_text);
+        let format = params.response_format();
+        match format {
+            ResponseFormat::Text => {}
+            ResponseFormat::Json => {
+                request_body["response_format"] = serde_json::json!({ "type": "json_object" });
+            }
+            ResponseFormat::JsonSchema => {
+                request_body["response_format"] = serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": {
+                        "name": schema_name(&node.id),
+                        "strict": params.strict,
+                        "schema": params.schema.clone().unwrap_or_default()
+                    }
+                });
+            }
         }
+        let validator = params.validator()?;
 
-        let response_json: Value = serde_json::from_str(&response_text)
-            .context("Failed to parse LLM response")?;
+        let mut attempt = 0;
+        let (content, usage, json) = loop {
+            let (content, usage) = complete(global, &base_url, &api_key, &request_body).await?;
+            if format == ResponseFormat::Text {
+                break (content, usage, None);
+            }
+            let problem = match parse_json(&content) {
+                Ok(json) => match schema_errors(validator.as_ref(), &json) {
+                    None => break (content, usage, Some(json)),
+                    Some(errors) => format!("It does not match the required schema: {}", errors),
+                },
+                Err(e) => format!("It is not valid JSON: {}", e),
+            };
+            if attempt >= params.repair_attempts {
+                anyhow::bail!("LLM reply rejected after {} attempt(s). {}.\nReply: {}", attempt + 1, problem, content);
+            }
+            attempt += 1;
+            log::warn!("LLM reply for {} rejected, asking for a repair ({}/{}): {}", node.id, attempt, params.repair_attempts, problem);
 
-        let content = response_json["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-
-        let usage = response_json.get("usage").cloned().unwrap_or(Value::Null);
+            // Show the model its reply and what is wrong with it
+            let messages = request_body["messages"].as_array_mut().context("messages is an array")?;
+            messages.push(serde_json::json!({ "role": "assistant", "content": content }));
+            messages.push(serde_json::json!({
+                "role": "user",
+                "content": format!("Your reply was rejected. {}. Reply again with only the corrected JSON.", problem)
+            }));
+        };
 
-        let result = serde_json::json!({
+        let mut result = serde_json::json!({
             "content": content,
             "model": model,
             "usage": usage
         });
+        if let Some(json) = json {
+            result["json"] = json;
+            result["attempts"] = serde_json::json!(attempt + 1);
+        }
 
         Ok(NodeOutput {
             status: "success".to_string(),
             output: result,
         })
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<LlmParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        let params: LlmParams = parse_params(node)?;
+        match params.response_format() {
+            ResponseFormat::Text if params.schema.is_some() => {
+                anyhow::bail!("LLM node '{}': 'schema' needs response_format 'json' or 'json_schema'", node.id)
+            }
+            ResponseFormat::JsonSchema if params.schema.is_none() => {
+                anyhow::bail!("LLM node '{}': response_format 'json_schema' requires 'schema'", node.id)
+            }
+            ResponseFormat::JsonSchema if params.strict => {
+                if let Some(problem) = params.schema.as_ref().and_then(|schema| strict_problem(schema, "")) {
+                    anyhow::bail!(
+                        "LLM node '{}': 'schema' is not valid for strict structured outputs: {} (or set 'strict: false')",
+                        node.id,
+                        problem
+                    );
+                }
+            }
+            _ => {}
+        }
+        params
+            .validator()
+            .with_context(|| format!("LLM node '{}'", node.id))
+            .map(drop)
+    }
+}
+
+/// One chat completion: the reply's content and the token usage
+async fn complete(global: &GlobalMemory, base_url: &str, api_key: &str, request_body: &Value) -> Result<(String, Value)> {
+    let request = global
+        .http()
+        .client()
+        .post(format!("{}/chat/completions", base_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .json(request_body);
+    let response = global
+        .http()
+        .send(request)
+        .await
+        .context("Failed to call LLM API")?;
+
+    let status = response.status();
+    let response_text = response.text().await?;
+
+    if !status.is_success() {
+        anyhow::bail!("LLM API error ({}): {}", status, response_text);
+    }
+
+    let response_json: Value = serde_json::from_str(&response_text)
+        .context("Failed to parse LLM response")?;
+
+    let content = response_json["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap_or("")
+        .to_string();
+
+    let usage = response_json.get("usage").cloned().unwrap_or(Value::Null);
+    Ok((content, usage))
+}
+
+/// Models often wrap JSON in a Markdown code fence, even in JSON mode
+fn parse_json(content: &str) -> serde_json::Result<Value> {
+    let content = content.trim();
+    let unfenced = content
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map(|inner| inner.trim_start_matches(|c: char| c.is_ascii_alphanumeric()).trim());
+    serde_json::from_str(unfenced.unwrap_or(content))
+}
+
+fn schema_errors(validator: Option<&jsonschema::Validator>, json: &Value) -> Option<String> {
+    let errors: Vec<String> = validator?
+        .iter_errors(json)
+        .map(|e| match e.instance_path.to_string() {
+            path if path.is_empty() => e.to_string(),
+            path => format!("{} at '{}'", e, path),
+        })
+        .collect();
+    (!errors.is_empty()).then(|| errors.join("; "))
+}
+
+/// Structured outputs need a name of `[a-zA-Z0-9_-]`, at most 64 characters
+fn schema_name(node_id: &str) -> String {
+    node_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .take(64)
+        .collect()
+}
+
+/// The first object in `schema` that strict mode would reject: every object
+/// with `properties` must set `additionalProperties: false` and require them all
+fn strict_problem(schema: &Value, path: &str) -> Option<String> {
+    let Value::Object(map) = schema else {
+        return None;
+    };
+    if let Some(Value::Object(properties)) = map.get("properties") {
+        let at = if path.is_empty() { "the root object".to_string() } else { format!("'{}'", path) };
+        if map.get("additionalProperties") != Some(&Value::Bool(false)) {
+            return Some(format!("{} needs 'additionalProperties: false'", at));
+        }
+        let required: Vec<&str> = map
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|r| r.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        if let Some(missing) = properties.keys().find(|key| !required.contains(&key.as_str())) {
+            return Some(format!("{} must list '{}' in 'required'", at, missing));
+        }
+    }
+    map.iter().find_map(|(key, value)| match value {
+        Value::Object(_) => strict_problem(value, &join_path(path, key)),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .find_map(|(i, item)| strict_problem(item, &join_path(path, &format!("{}/{}", key, i)))),
+        _ => None,
+    })
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}/{}", path, key) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_json_strips_code_fences() {
+        assert_eq!(parse_json(r#" {"a": 1} "#).unwrap(), json!({"a": 1}));
+        assert_eq!(parse_json("```json\n{\"a\": 1}\n```").unwrap(), json!({"a": 1}));
+        assert_eq!(parse_json("```\n[1, 2]\n```").unwrap(), json!([1, 2]));
+        assert!(parse_json("Sure! {\"a\": 1}").is_err());
+    }
+
+    #[test]
+    fn schema_errors_name_the_failing_path() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"items": {"type": "array", "items": {"type": "integer"}}},
+            "required": ["items"]
+        });
+        let validator = jsonschema::validator_for(&schema).unwrap();
+
+        assert_eq!(schema_errors(Some(&validator), &json!({"items": [1]})), None);
+        assert_eq!(schema_errors(None, &json!("anything")), None);
+        let errors = schema_errors(Some(&validator), &json!({"items": [1, "x"]})).unwrap();
+        assert!(errors.contains("at '/items/1'"), "{}", errors);
+        let errors = schema_errors(Some(&validator), &json!({})).unwrap();
+        assert!(errors.contains("items"), "{}", errors);
+    }
+
+    #[test]
+    fn strict_problem_checks_nested_objects() {
+        let ok = json!({
+            "type": "object",
+            "properties": {"a": {"type": "object", "properties": {"b": {"type": "string"}}, "required": ["b"], "additionalProperties": false}},
+            "required": ["a"],
+            "additionalProperties": false
+        });
+        assert_eq!(strict_problem(&ok, ""), None);
+
+        let open = json!({"type": "object", "properties": {"a": {"type": "string"}}, "required": ["a"]});
+        assert_eq!(strict_problem(&open, "").unwrap(), "the root object needs 'additionalProperties: false'");
+
+        let mut nested = ok.clone();
+        nested["properties"]["a"]["required"] = json!([]);
+        assert_eq!(strict_problem(&nested, "").unwrap(), "'properties/a' must list 'b' in 'required'");
+    }
+
+    #[test]
+    fn schema_name_is_sanitized_and_capped() {
+        assert_eq!(schema_name("extract.v2 step"), "extract_v2_step");
+        assert_eq!(schema_name(&"x".repeat(100)).len(), 64);
+    }
 }
@@ -1,14 +1,42 @@
-use crate::nodes::NodeExecutor;
+use crate::nodes::{params_schema, parse_params, ExecutorRegistry, NodeExecutor, WeakRegistry};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
 use serde_json::Value;
 use crate::engine::Engine;
 use crate::schema::Workflow;
+use crate::migrate;
 
-pub struct LoopExecutor;
+pub struct LoopExecutor {
+    /// Executors for the steps; weak because the registry owns this executor
+    executors: WeakRegistry,
+}
+
+impl LoopExecutor {
+    pub fn new(executors: &ExecutorRegistry) -> Self {
+        Self { executors: executors.downgrade() }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LoopParams {
+    pub items: LoopItems,
+    /// Nodes run once per item, with `{{ global.loop.item }}` / `index` / `total` set
+    pub steps: Vec<Node>,
+}
+
+/// A literal list, or a template rendering to a JSON array
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum LoopItems {
+    List(Vec<Value>),
+    Template(String),
+}
 
 #[async_trait]
 impl NodeExecutor for LoopExecutor {
@@ -19,34 +47,22 @@ impl NodeExecutor for LoopExecutor {
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
         let template = TemplateEngine::new(global.clone(), nodes.clone());
+        let params: LoopParams = parse_params(node)?;
+        let executors = self.executors.upgrade()?;
         
         // 1. Get items to iterate
-        let items_param = node.params
-            .get("items")
-            .context("Loop node requires 'items' parameter")?;
-            
         // If items is a string (template), render and parse it
-        let items: Vec<Value> = if let Some(s) = items_param.as_str() {
-            let rendered = template.render(s)?;
-            serde_json::from_str(&rendered)
-                .or_else(|_| {
-                    // If not JSON, maybe it's just a string we want to treat as a single item list?
-                    // Or maybe it failed to parse. Let's try to see if it's a JSON array.
-                    anyhow::bail!("Failed to parse 'items' as JSON array: {}", rendered)
-                })?
-        } else if let Some(arr) = items_param.as_array() {
-            arr.clone()
-        } else {
-            anyhow::bail!("'items' parameter must be an array")
+        let items: Vec<Value> = match params.items {
+            LoopItems::Template(s) => {
+                let rendered = template.render(&s)?;
+                serde_json::from_str(&rendered)
+                    .with_context(|| format!("Failed to parse 'items' as JSON array: {}", rendered))?
+            }
+            LoopItems::List(items) => items,
         };
 
         // 2. Get steps (sub-workflow nodes)
-        let steps_val = node.params
-            .get("steps")
-            .context("Loop node requires 'steps' parameter")?;
-            
-        let steps: Vec<Node> = serde_json::from_value(steps_val.clone())
-            .context("Failed to parse 'steps' as list of Nodes")?;
+        let steps = params.steps;
 
         log::info!("Looping over {} items with {} steps", items.len(), steps.len());
 
@@ -59,9 +75,10 @@ impl NodeExecutor for LoopExecutor {
             // Create a sub-workflow
             let sub_workflow = Workflow {
                 name: format!("{}_iter_{}", node.name, index),
-                version: "1.0".to_string(),
-                global: std::collections::HashMap::new(), // We'll inject global memory manually
+                version: migrate::CURRENT_VERSION.to_string(),
                 nodes: steps.clone(),
+                // Global memory is shared with the parent run rather than seeded
+                ..Default::default()
             };
 
             // Use the SAME global memory to allow state sharing and accumulation across iterations.
@@ -74,9 +91,10 @@ impl NodeExecutor for LoopExecutor {
                 "item": item,
                 "total": items.len()
             });
-            iter_global.set("loop".to_string(), loop_ctx);
+            iter_global.set("loop".to_string(), loop_ctx)?;
 
-            let engine = Engine::new_with_memory(sub_workflow, iter_global);
+            let engine = Engine::new_with_memory(sub_workflow, iter_global)
+                .with_executors(executors.clone());
             
             // Execute sub-workflow
             engine.execute().await?;
@@ -84,7 +102,7 @@ impl NodeExecutor for LoopExecutor {
             // Collect outputs from this iteration
             // We might want to return the output of the LAST node, or a map of all nodes?
             // Let's return a map of all node outputs for this iteration.
-            let node_outputs: std::collections::HashMap<String, Value> = engine.get_node_memory().get_all_values();
+            let node_outputs: std::collections::HashMap<String, Value> = engine.get_node_memory().get_all_values()?;
             results.push(serde_json::json!(node_outputs));
         }
 
@@ -95,4 +113,17 @@ impl NodeExecutor for LoopExecutor {
             }),
         })
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<LoopParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        let params: LoopParams = parse_params(node)?;
+        let executors = self.executors.upgrade()?;
+        for step in &params.steps {
+            executors.validate(step)?;
+        }
+        Ok(())
+    }
 }
@@ -1,9 +1,10 @@
 use crate::memory::{GlobalMemory, NodeMemory, NodeOutput};
-use crate::nodes::NodeExecutor;
+use crate::nodes::{params_schema, parse_params, NodeExecutor};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::process::Stdio;
@@ -12,6 +13,30 @@ use tokio::process::Command;
 
 pub struct McpExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct McpParams {
+    pub server: McpServer,
+    /// Tool to call
+    pub tool: String,
+    /// Tool arguments; strings are templated
+    #[serde(default = "empty_object")]
+    pub arguments: Value,
+}
+
+/// MCP server started over stdio
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct McpServer {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+fn empty_object() -> Value {
+    json!({})
+}
+
 #[derive(Serialize, Deserialize)]
 struct JsonRpcRequest {
     jsonrpc: String,
@@ -37,30 +62,16 @@ impl NodeExecutor for McpExecutor {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
+        let params: McpParams = parse_params(node)?;
         let template = TemplateEngine::new(global.clone(), nodes.clone());
-
-        // Parse parameters
-        let server_config = node.params.get("server").context("Missing 'server' param")?;
-        let command_str = server_config
-            .get("command")
-            .and_then(|v| v.as_str())
-            .context("Missing 'server.command'")?;
-        
-        let args: Vec<String> = server_config
-            .get("args")
-            .and_then(|v| v.as_array())
-            .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
-            .unwrap_or_default();
-
-        let tool_name = node.params.get("tool").and_then(|v| v.as_str()).context("Missing 'tool' param")?;
-        let raw_tool_args = node.params.get("arguments").cloned().unwrap_or(json!({}));
+        let tool_name = params.tool.as_str();
 
         // Render tool arguments
-        let tool_args = render_value(&template, &raw_tool_args)?;
+        let tool_args = render_value(&template, &params.arguments)?;
 
         // Spawn server process
-        let mut child = Command::new(command_str)
-            .args(&args)
+        let mut child = Command::new(&params.server.command)
+            .args(&params.server.args)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::inherit())
@@ -153,6 +164,14 @@ impl NodeExecutor for McpExecutor {
             })
         }
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<McpParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        parse_params::<McpParams>(node).map(drop)
+    }
 }
 
 fn render_value(template: &TemplateEngine, value: &Value) -> Result<Value> {
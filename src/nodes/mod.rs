@@ -1,7 +1,10 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 
 mod shell;
 mod http;
@@ -14,6 +17,14 @@ mod loop_node;
 mod input;
 mod assign;
 mod mcp;
+mod workflow;
+mod registry;
+mod plugin;
+mod wasm;
+mod environment;
+mod process;
+mod pagination;
+pub mod runtime;
 
 pub use shell::ShellExecutor;
 pub use http::HttpExecutor;
@@ -21,11 +32,14 @@ pub use script::ScriptExecutor;
 pub use llm::LlmExecutor;
 pub use transform::TransformExecutor;
 pub use file::FileExecutor;
-pub use control::{DelayExecutor, SwitchExecutor};
+pub use control::{DelayExecutor, JoinExecutor, SwitchExecutor};
 pub use loop_node::LoopExecutor;
 pub use input::InputExecutor;
 pub use assign::AssignExecutor;
 pub use mcp::McpExecutor;
+pub use workflow::WorkflowExecutor;
+pub use registry::{ExecutorRegistry, WeakRegistry};
+pub use plugin::{load_plugins, register_plugin, PluginConfig, PluginExecutor, PluginSpec};
 
 #[async_trait]
 pub trait NodeExecutor: Send + Sync {
@@ -35,22 +49,29 @@ pub trait NodeExecutor: Send + Sync {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput>;
-}
 
-pub fn get_executor(node_type: &str) -> Result<Box<dyn NodeExecutor>> {
-    match node_type {
-        "shell" => Ok(Box::new(ShellExecutor)),
-        "http" => Ok(Box::new(HttpExecutor)),
-        "delay" => Ok(Box::new(DelayExecutor)),
-        "switch" => Ok(Box::new(SwitchExecutor)),
-        "script" => Ok(Box::new(ScriptExecutor)),
-        "llm" => Ok(Box::new(LlmExecutor)),
-        "transform" => Ok(Box::new(TransformExecutor)),
-        "file" => Ok(Box::new(FileExecutor)),
-        "input" => Ok(Box::new(InputExecutor)),
-        "loop" => Ok(Box::new(LoopExecutor)),
-        "assign" => Ok(Box::new(AssignExecutor)),
-        "mcp" => Ok(Box::new(McpExecutor)),
-        _ => anyhow::bail!("Unknown node type: {}", node_type),
+    /// JSON Schema of this node type's `params`; any params by default
+    fn params_schema(&self, _generator: &mut SchemaGenerator) -> Schema {
+        Schema::default()
+    }
+
+    /// Check `params` before the run starts, so typos fail fast; nothing is checked by default
+    fn validate(&self, _node: &Node) -> Result<()> {
+        Ok(())
     }
 }
+
+/// Deserialize a node's `params` into its typed form, rejecting unknown or mistyped fields
+pub fn parse_params<T: DeserializeOwned>(node: &Node) -> Result<T> {
+    let params = match &node.params {
+        Value::Null => Value::Object(Default::default()),
+        other => other.clone(),
+    };
+    serde_json::from_value(params)
+        .with_context(|| format!("Invalid params for {} node '{}'", node.node_type, node.id))
+}
+
+/// Schema of a params struct, registered in the generator's definitions
+pub fn params_schema<T: JsonSchema>(generator: &mut SchemaGenerator) -> Schema {
+    generator.subschema_for::<T>()
+}
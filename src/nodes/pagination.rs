@@ -0,0 +1,256 @@
+//! Pagination for HTTP nodes: follow a listing page by page and collect its items.
+
+use anyhow::{Context, Result};
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Pagination {
+    pub mode: PageMode,
+    /// JSONPath to the items of a page; by default the body itself, which must be an array
+    #[serde(default)]
+    pub items: Option<String>,
+    /// JSONPath to the next page's URL (`next_link`) or cursor (`cursor`)
+    #[serde(default)]
+    pub next: Option<String>,
+    /// Query parameter carrying the page number, offset or cursor
+    #[serde(default)]
+    pub param: Option<String>,
+    /// First page number or offset; 1 for `page`, 0 for `offset`
+    #[serde(default)]
+    pub start: Option<u64>,
+    /// Page size: the step for `offset`; a shorter page ends the listing
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Query parameter to send `size` in
+    #[serde(default)]
+    pub size_param: Option<String>,
+    /// Stop after this many pages; `output.has_more` tells whether more were left
+    #[serde(default = "default_max_pages")]
+    pub max_pages: usize,
+}
+
+fn default_max_pages() -> usize {
+    100
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PageMode {
+    /// The next page's URL is in the body, at `next`
+    NextLink,
+    /// The next page's URL is the `rel="next"` entry of the `Link` header
+    LinkHeader,
+    /// Page number in `param`, counting up from `start`
+    Page,
+    /// Item offset in `param`, stepping by `size`
+    Offset,
+    /// The body's `next` value is sent back in `param`
+    Cursor,
+}
+
+/// Where the next page is
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NextPage {
+    Url(String),
+    /// Query parameters replacing those of the same name in the first request
+    Query(Vec<(String, String)>),
+}
+
+impl Pagination {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_pages < 1 {
+            anyhow::bail!("pagination 'max_pages' must be at least 1");
+        }
+        let require = |field: Option<&str>, name: &str| match field {
+            Some(_) => Ok(()),
+            None => Err(anyhow::anyhow!("pagination mode '{}' requires '{}'", self.mode.as_str(), name)),
+        };
+        match self.mode {
+            PageMode::NextLink => require(self.next.as_deref(), "next"),
+            PageMode::LinkHeader => Ok(()),
+            PageMode::Page => require(self.param.as_deref(), "param"),
+            PageMode::Offset => {
+                require(self.param.as_deref(), "param")?;
+                require(self.size.map(|_| ""), "size")
+            }
+            PageMode::Cursor => {
+                require(self.param.as_deref(), "param")?;
+                require(self.next.as_deref(), "next")
+            }
+        }
+    }
+
+    /// Query parameters of the first request
+    pub fn first_query(&self) -> Vec<(String, String)> {
+        let mut query = Vec::new();
+        if let (Some(param), Some(start)) = (&self.param, self.counter(0)) {
+            query.push((param.clone(), start.to_string()));
+        }
+        if let (Some(param), Some(size)) = (&self.size_param, self.size) {
+            query.push((param.clone(), size.to_string()));
+        }
+        query
+    }
+
+    /// Page number or offset of the `index`th page (from 0), for counting modes
+    fn counter(&self, index: u64) -> Option<u64> {
+        match self.mode {
+            PageMode::Page => Some(self.start.unwrap_or(1) + index),
+            PageMode::Offset => Some(self.start.unwrap_or(0) + index * self.size.unwrap_or(0)),
+            _ => None,
+        }
+    }
+
+    /// The items of one page
+    pub fn items(&self, body: &Value) -> Result<Vec<Value>> {
+        let selected = match &self.items {
+            Some(path) => jsonpath_lib::select(body, path)
+                .map_err(|e| anyhow::anyhow!("{:?}", e))
+                .with_context(|| format!("JSONPath '{}' evaluation failed", path))?,
+            None => vec![body],
+        };
+        Ok(match selected.as_slice() {
+            [Value::Array(items)] => items.clone(),
+            [Value::Null] | [] => Vec::new(),
+            values if self.items.is_some() => values.iter().map(|v| (*v).clone()).collect(),
+            _ => anyhow::bail!("Paginated response is not an array; set 'pagination.items' to the JSONPath of its items"),
+        })
+    }
+
+    /// Where the page after the `index`th one (from 0) is, if there is one
+    pub fn next(&self, index: u64, url: &str, link: Option<&str>, body: &Value, count: usize) -> Result<Option<NextPage>> {
+        if count == 0 {
+            return Ok(None);
+        }
+        let short_page = self.size.is_some_and(|size| (count as u64) < size);
+        Ok(match self.mode {
+            PageMode::NextLink => self.select_next(body)?.map(|next| resolve(url, &next)).transpose()?.map(NextPage::Url),
+            PageMode::LinkHeader => link.and_then(next_link).map(|next| resolve(url, &next)).transpose()?.map(NextPage::Url),
+            PageMode::Page | PageMode::Offset if short_page => None,
+            PageMode::Page | PageMode::Offset => {
+                let mut query = self.first_query();
+                query[0].1 = self.counter(index + 1).unwrap_or_default().to_string();
+                Some(NextPage::Query(query))
+            }
+            PageMode::Cursor => self.select_next(body)?.map(|cursor| {
+                let mut query = self.first_query();
+                query.insert(0, (self.param.clone().unwrap_or_default(), cursor));
+                NextPage::Query(query)
+            }),
+        })
+    }
+
+    /// `next` as a non-empty string; numbers are accepted for cursors
+    fn select_next(&self, body: &Value) -> Result<Option<String>> {
+        let Some(path) = &self.next else { return Ok(None) };
+        let selected = jsonpath_lib::select(body, path)
+            .map_err(|e| anyhow::anyhow!("{:?}", e))
+            .with_context(|| format!("JSONPath '{}' evaluation failed", path))?;
+        Ok(match selected.first() {
+            Some(Value::String(s)) if !s.is_empty() => Some(s.clone()),
+            Some(Value::Number(n)) => Some(n.to_string()),
+            _ => None,
+        })
+    }
+}
+
+impl PageMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PageMode::NextLink => "next_link",
+            PageMode::LinkHeader => "link_header",
+            PageMode::Page => "page",
+            PageMode::Offset => "offset",
+            PageMode::Cursor => "cursor",
+        }
+    }
+}
+
+/// Next links may be relative to the page they came from
+fn resolve(base: &str, next: &str) -> Result<String> {
+    let base = Url::parse(base).with_context(|| format!("Invalid URL: {}", base))?;
+    Ok(base.join(next).with_context(|| format!("Invalid next page URL: {}", next))?.to_string())
+}
+
+/// The `rel="next"` target of a `Link` header (RFC 8288)
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (target, params) = link.split_once(';')?;
+        let is_next = params.split(';').any(|param| {
+            let param = param.trim().replace(' ', "");
+            param == "rel=\"next\"" || param == "rel=next"
+        });
+        is_next.then(|| target.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pagination(value: Value) -> Pagination {
+        let pagination: Pagination = serde_json::from_value(value).unwrap();
+        pagination.validate().unwrap();
+        pagination
+    }
+
+    fn query(pairs: &[(&str, &str)]) -> Option<NextPage> {
+        Some(NextPage::Query(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()))
+    }
+
+    const URL: &str = "https://api.example.com/items?page=1";
+
+    #[test]
+    fn page_and_offset_count_up_until_a_short_page() {
+        let page = pagination(json!({"mode": "page", "param": "page", "size": 2, "size_param": "per_page"}));
+        assert_eq!(page.first_query(), [("page".to_string(), "1".to_string()), ("per_page".to_string(), "2".to_string())]);
+        assert_eq!(page.next(0, URL, None, &json!([]), 2).unwrap(), query(&[("page", "2"), ("per_page", "2")]));
+        assert_eq!(page.next(1, URL, None, &json!([]), 1).unwrap(), None);
+
+        let offset = pagination(json!({"mode": "offset", "param": "offset", "size": 50, "start": 10}));
+        assert_eq!(offset.next(2, URL, None, &json!([]), 50).unwrap(), query(&[("offset", "160")]));
+        assert_eq!(offset.next(0, URL, None, &json!([]), 0).unwrap(), None);
+    }
+
+    #[test]
+    fn next_link_is_resolved_against_the_page() {
+        let p = pagination(json!({"mode": "next_link", "next": "$.links.next", "items": "$.data"}));
+        let body = json!({"data": [1], "links": {"next": "/items?page=2"}});
+        assert_eq!(p.next(0, URL, None, &body, 1).unwrap(), Some(NextPage::Url("https://api.example.com/items?page=2".to_string())));
+        assert_eq!(p.next(0, URL, None, &json!({"data": [1], "links": {"next": ""}}), 1).unwrap(), None);
+    }
+
+    #[test]
+    fn link_header_follows_rel_next() {
+        let p = pagination(json!({"mode": "link_header"}));
+        let link = r#"<https://api.example.com/items?page=1>; rel="prev", <https://api.example.com/items?page=3>; rel="next""#;
+        assert_eq!(p.next(0, URL, Some(link), &json!([1]), 1).unwrap(), Some(NextPage::Url("https://api.example.com/items?page=3".to_string())));
+        assert_eq!(p.next(0, URL, Some(r#"<x>; rel="prev""#), &json!([1]), 1).unwrap(), None);
+        assert_eq!(p.next(0, URL, None, &json!([1]), 1).unwrap(), None);
+    }
+
+    #[test]
+    fn cursor_is_sent_back_in_param() {
+        let p = pagination(json!({"mode": "cursor", "param": "after", "next": "$.cursor", "items": "$.items"}));
+        assert_eq!(p.first_query(), []);
+        assert_eq!(p.next(0, URL, None, &json!({"items": [1], "cursor": 42}), 1).unwrap(), query(&[("after", "42")]));
+        assert_eq!(p.next(0, URL, None, &json!({"items": [1], "cursor": null}), 1).unwrap(), None);
+    }
+
+    #[test]
+    fn items_and_validation() {
+        let p = pagination(json!({"mode": "link_header"}));
+        assert_eq!(p.items(&json!([1, 2])).unwrap(), [json!(1), json!(2)]);
+        assert!(p.items(&json!({"a": 1})).is_err());
+
+        let invalid: Pagination = serde_json::from_value(json!({"mode": "page", "param": "p", "max_pages": 0})).unwrap();
+        assert!(invalid.validate().is_err());
+        let invalid: Pagination = serde_json::from_value(json!({"mode": "offset", "param": "o"})).unwrap();
+        assert!(invalid.validate().unwrap_err().to_string().contains("'size'"));
+    }
+}
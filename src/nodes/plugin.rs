@@ -0,0 +1,384 @@
+//! Node types implemented by external programs.
+//!
+//! A plugin is a subprocess speaking JSON lines over stdio: one request per
+//! line on stdin, one response per line on stdout.
+//!
+//! - `{"method":"describe"}` → `{"types":[{"type":"our_crm","description":"...","params":{<JSON Schema>}}]}`
+//! - `{"method":"execute","node":{"id":..,"type":..,"name":..,"params":{..}}}`
+//!   → `{"status":"success","output":..}` or `{"error":"..."}`
+//!
+//! `describe` runs once when the plugin is loaded; every `execute` starts a
+//! fresh process. Params are templated by the engine before they are sent.
+//! A process that does not reply within the plugin's `timeout_ms` is killed;
+//! `describe` is then sent once more to a new one, while `execute` fails
+//! because the plugin may already have acted on it.
+
+use crate::memory::{GlobalMemory, NodeMemory, NodeOutput};
+use crate::nodes::{ExecutorRegistry, NodeExecutor};
+use crate::schema::Node;
+use crate::template::TemplateEngine;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use schemars::{Schema, SchemaGenerator};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+
+/// Plugins file passed with `--plugins`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    pub plugins: Vec<PluginSpec>,
+}
+
+/// How to start one plugin
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PluginSpec {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Working directory; the plugins file's directory by default
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// How long to wait for a reply before the process is killed
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    30_000
+}
+
+#[derive(Deserialize)]
+struct Description {
+    types: Vec<TypeDescription>,
+}
+
+#[derive(Deserialize)]
+struct TypeDescription {
+    #[serde(rename = "type")]
+    node_type: String,
+    #[serde(default)]
+    description: Option<String>,
+    /// JSON Schema of the type's params; anything goes when omitted
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct ExecuteResponse {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    output: Value,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Executes one node type of a plugin
+pub struct PluginExecutor {
+    plugin: Arc<PluginSpec>,
+    schema: Option<Value>,
+    validator: Option<jsonschema::Validator>,
+}
+
+impl PluginSpec {
+    /// Send one request to a fresh plugin process and read its single-line reply.
+    /// Only `idempotent` requests are retried after a timeout.
+    async fn call(&self, request: &Value, idempotent: bool) -> Result<String> {
+        let timeout = Duration::from_millis(self.timeout_ms);
+        let attempts = if idempotent { 2 } else { 1 };
+        for attempt in 1..=attempts {
+            let mut child = self.spawn()?;
+            let reply = tokio::time::timeout(timeout, self.exchange(&mut child, request)).await;
+            child.kill().await.ok();
+            match reply {
+                Ok(reply) => return reply,
+                Err(_) if attempt < attempts => log::warn!(
+                    "Plugin '{}' did not reply within {} ms; killed it and starting a new process",
+                    self.name,
+                    self.timeout_ms
+                ),
+                Err(_) => {}
+            }
+        }
+        anyhow::bail!(
+            "Plugin '{}' did not reply within {} ms{}",
+            self.name,
+            self.timeout_ms,
+            if idempotent { " (tried twice)" } else { "" }
+        )
+    }
+
+    fn spawn(&self) -> Result<Child> {
+        let mut command = Command::new(&self.command);
+        command
+            .args(&self.args)
+            .envs(&self.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        command
+            .spawn()
+            .with_context(|| format!("Failed to start plugin '{}' ({})", self.name, self.command))
+    }
+
+    async fn exchange(&self, child: &mut Child, request: &Value) -> Result<String> {
+        let mut stdin = child.stdin.take().context("Failed to open stdin")?;
+        let stdout = child.stdout.take().context("Failed to open stdout")?;
+
+        stdin.write_all(serde_json::to_string(request)?.as_bytes()).await?;
+        stdin.write_all(b"\n").await?;
+        stdin.flush().await?;
+        drop(stdin); // EOF tells the plugin no more requests follow
+
+        let line = BufReader::new(stdout)
+            .lines()
+            .next_line()
+            .await?
+            .with_context(|| format!("Plugin '{}' exited without replying", self.name))?;
+        Ok(line)
+    }
+}
+
+/// Read a plugins file and register every node type its plugins describe.
+/// Types that are already registered (built-ins included) are left alone.
+pub async fn load_plugins(registry: &ExecutorRegistry, path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read plugins file `{:?}`", path))?;
+    let config: PluginConfig = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse plugins file `{:?}`", path))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    for mut spec in config.plugins {
+        spec.cwd = Some(base_dir.join(spec.cwd.take().unwrap_or_default()));
+        register_plugin(registry, spec).await?;
+    }
+    Ok(())
+}
+
+/// Ask a plugin which node types it provides and register them
+pub async fn register_plugin(registry: &ExecutorRegistry, spec: PluginSpec) -> Result<()> {
+    let reply = spec.call(&json!({ "method": "describe" }), true).await?;
+    let description: Description = serde_json::from_str(&reply)
+        .with_context(|| format!("Invalid describe reply from plugin '{}': {}", spec.name, reply))?;
+
+    let plugin = Arc::new(spec);
+    for described in description.types {
+        if registry.contains(&described.node_type) {
+            log::warn!(
+                "Plugin '{}' type '{}' is already registered; keeping the existing executor",
+                plugin.name,
+                described.node_type
+            );
+            continue;
+        }
+
+        let mut schema = described.params;
+        if let (Some(Value::Object(schema)), Some(text)) = (&mut schema, described.description) {
+            schema.entry("description").or_insert(Value::String(text));
+        }
+        let validator = schema
+            .as_ref()
+            .map(jsonschema::validator_for)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Plugin '{}' type '{}' has an invalid params schema", plugin.name, described.node_type))?;
+
+        log::info!("Registered node type '{}' from plugin '{}'", described.node_type, plugin.name);
+        registry.register(
+            described.node_type,
+            PluginExecutor { plugin: plugin.clone(), schema, validator },
+        );
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl NodeExecutor for PluginExecutor {
+    async fn execute(
+        &self,
+        node: &Node,
+        global: &GlobalMemory,
+        nodes: &NodeMemory,
+    ) -> Result<NodeOutput> {
+        let template = TemplateEngine::new(global.clone(), nodes.clone());
+        let params = template.render_value(&node.params)?;
+        self.check_params(node, &params, false)?;
+
+        let request = json!({
+            "method": "execute",
+            "node": {
+                "id": node.id,
+                "type": node.node_type,
+                "name": node.name,
+                "params": params,
+            },
+        });
+        let reply = self.plugin.call(&request, false).await?;
+        let response: ExecuteResponse = serde_json::from_str(&reply)
+            .with_context(|| format!("Invalid execute reply from plugin '{}': {}", self.plugin.name, reply))?;
+
+        if let Some(error) = response.error {
+            anyhow::bail!("Plugin '{}' failed: {}", self.plugin.name, error);
+        }
+        Ok(NodeOutput {
+            status: response.status.unwrap_or_else(|| "success".to_string()),
+            output: response.output,
+        })
+    }
+
+    fn params_schema(&self, _generator: &mut SchemaGenerator) -> Schema {
+        self.schema
+            .clone()
+            .and_then(|s| Schema::try_from(s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Templated values are checked once rendered, right before `execute`
+    fn validate(&self, node: &Node) -> Result<()> {
+        let params = match &node.params {
+            Value::Null => json!({}),
+            other => other.clone(),
+        };
+        self.check_params(node, &params, true)
+    }
+}
+
+impl PluginExecutor {
+    /// Check params against the plugin's schema; with `allow_templates`,
+    /// strings still holding `{{ }}` are accepted wherever they appear
+    fn check_params(&self, node: &Node, params: &Value, allow_templates: bool) -> Result<()> {
+        let Some(validator) = &self.validator else {
+            return Ok(());
+        };
+        let errors: Vec<String> = validator
+            .iter_errors(params)
+            .filter(|e| !(allow_templates && is_template(&e.instance)))
+            .map(|e| match e.instance_path.to_string() {
+                path if path.is_empty() => e.to_string(),
+                path => format!("{} at '{}'", e, path),
+            })
+            .collect();
+        if !errors.is_empty() {
+            anyhow::bail!(
+                "Invalid params for {} node '{}': {}",
+                node.node_type,
+                node.id,
+                errors.join("; ")
+            );
+        }
+        Ok(())
+    }
+}
+
+fn is_template(value: &Value) -> bool {
+    matches!(value, Value::String(s) if s.contains("{{"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Engine;
+
+    /// Write a `sh` plugin to a temp dir; each start is logged to `starts`
+    fn plugin(script: &str, timeout_ms: u64) -> (PluginSpec, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("workflow-engine-plugin-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("plugin.sh"), format!("echo start >> starts\nread -r line\n{}", script)).unwrap();
+        let spec = PluginSpec {
+            name: "test".to_string(),
+            command: "sh".to_string(),
+            args: vec!["plugin.sh".to_string()],
+            env: HashMap::new(),
+            cwd: Some(dir.clone()),
+            timeout_ms,
+        };
+        (spec, dir)
+    }
+
+    fn starts(dir: &Path) -> usize {
+        std::fs::read_to_string(dir.join("starts")).unwrap_or_default().lines().count()
+    }
+
+    const DESCRIBE: &str = r#"{"types":[{"type":"counter","params":{"type":"object","properties":{"n":{"type":"integer"}},"required":["n"],"additionalProperties":false}}]}"#;
+
+    fn node(params: Value) -> Node {
+        serde_json::from_value(json!({"id": "c", "type": "counter", "params": params})).unwrap()
+    }
+
+    #[tokio::test]
+    async fn templated_params_are_checked_once_rendered() {
+        let script = format!(
+            "case \"$line\" in\n  *describe*) echo '{}' ;;\n  *) echo \"{{\\\"output\\\":$line}}\" ;;\nesac\n",
+            DESCRIBE
+        );
+        let (spec, dir) = plugin(&script, 5_000);
+        let registry = ExecutorRegistry::empty();
+        register_plugin(&registry, spec).await.unwrap();
+
+        registry.validate(&node(json!({"n": "{{ global.n }}"}))).unwrap();
+        registry.validate(&node(json!({"n": 3}))).unwrap();
+        assert!(registry.validate(&node(json!({"n": "three"}))).is_err());
+        assert!(registry.validate(&node(json!({}))).is_err());
+
+        let run = |n: Value| {
+            let workflow = serde_json::from_value(json!({
+                "name": "plugin", "version": "2.0", "global": {"n": n},
+                "nodes": [node(json!({"n": "{{ global.n }}"}))],
+            }))
+            .unwrap();
+            Engine::new(workflow).unwrap().with_executors(registry.clone())
+        };
+        let result = run(json!(5)).run().await.unwrap();
+        assert_eq!(result.node_outputs["c"]["node"]["params"], json!({"n": 5}));
+
+        let err = run(json!("five")).run().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid params for counter node 'c'"), "{:#}", err);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn execute_is_not_retried_after_a_timeout() {
+        let script = format!("case \"$line\" in\n  *describe*) echo '{}' ;;\n  *) exec sleep 5 ;;\nesac\n", DESCRIBE);
+        let (spec, dir) = plugin(&script, 300);
+        let registry = ExecutorRegistry::empty();
+        register_plugin(&registry, spec).await.unwrap();
+        assert_eq!(starts(&dir), 1);
+
+        let workflow = serde_json::from_value(json!({
+            "name": "plugin", "version": "2.0", "nodes": [node(json!({"n": 1}))],
+        }))
+        .unwrap();
+        let err = Engine::new(workflow).unwrap().with_executors(registry).run().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("did not reply within 300 ms"), "{:#}", err);
+        assert_eq!(starts(&dir), 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn describe_is_retried_after_a_timeout() {
+        // Hangs on its first start only
+        let script = format!("[ \"$(wc -l < starts)\" -eq 1 ] && exec sleep 5\necho '{}'\n", DESCRIBE);
+        let (spec, dir) = plugin(&script, 300);
+        let registry = ExecutorRegistry::empty();
+        register_plugin(&registry, spec).await.unwrap();
+        assert!(registry.contains("counter"));
+        assert_eq!(starts(&dir), 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,290 @@
+//! Child processes of shell and script nodes, with their output streamed.
+//!
+//! Every line goes to the log and to the run's event subscribers as soon as
+//! it is written. What is kept for the node output is capped per stream: past
+//! the cap the middle is replaced by a marker, keeping the start and the end
+//! (where errors usually are). The complete streams can be spilled to files.
+
+use crate::events::{OutputStream, RunEvent};
+use crate::memory::GlobalMemory;
+use crate::template::TemplateEngine;
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::Child;
+
+/// Longer lines are passed on in pieces of this size
+const MAX_LINE: usize = 64 * 1024;
+
+/// How much process output a node keeps
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Capture {
+    /// Bytes of stdout, and of stderr, kept in the node output
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+    /// Directory to write the complete stdout and stderr to (templated);
+    /// the paths are set as `output.stdout_file` / `output.stderr_file`
+    #[serde(default)]
+    pub spill_dir: Option<String>,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self { max_bytes: default_max_bytes(), spill_dir: None }
+    }
+}
+
+fn default_max_bytes() -> usize {
+    1024 * 1024
+}
+
+/// One captured stream
+pub struct Captured {
+    pub text: String,
+    /// Bytes dropped from the middle
+    pub truncated: usize,
+    pub file: Option<PathBuf>,
+}
+
+pub struct ProcessOutput {
+    pub status: ExitStatus,
+    pub stdout: Captured,
+    pub stderr: Captured,
+}
+
+impl ProcessOutput {
+    pub fn exit_code(&self) -> i32 {
+        self.status.code().unwrap_or(-1)
+    }
+
+    /// Node output with `stdout`/`stderr` (trimmed) and, when they apply,
+    /// `stdout_truncated`/`stdout_file` and the stderr equivalents
+    pub fn to_output(&self, success: bool) -> Value {
+        let mut output = serde_json::json!({
+            "stdout": self.stdout.text.trim(),
+            "stderr": self.stderr.text.trim(),
+            "exit_code": self.exit_code(),
+            "success": success
+        });
+        for (name, stream) in [("stdout", &self.stdout), ("stderr", &self.stderr)] {
+            if stream.truncated > 0 {
+                output[format!("{}_truncated", name)] = Value::from(stream.truncated);
+            }
+            if let Some(file) = &stream.file {
+                output[format!("{}_file", name)] = Value::String(file.to_string_lossy().to_string());
+            }
+        }
+        output
+    }
+}
+
+/// Feed `stdin` to a spawned child (stdout/stderr piped) and stream its output until it exits
+pub async fn run(
+    mut child: Child,
+    stdin: Option<Vec<u8>>,
+    global: &GlobalMemory,
+    capture: &Capture,
+    template: &TemplateEngine,
+) -> Result<ProcessOutput> {
+    let node = global.node().unwrap_or("?").to_string();
+    let spill_dir = capture.spill_dir.as_deref().map(|d| template.render(d)).transpose()?.map(PathBuf::from);
+    let spill_prefix = format!("{}-{}", node.replace('/', "_"), uuid::Uuid::new_v4());
+    if let Some(dir) = &spill_dir {
+        tokio::fs::create_dir_all(dir).await
+            .with_context(|| format!("Failed to create spill directory `{:?}`", dir))?;
+    }
+
+    // Written concurrently with reading, so a child that answers before it has
+    // read all of its input cannot deadlock on a full pipe
+    if let (Some(mut pipe), Some(stdin)) = (child.stdin.take(), stdin) {
+        tokio::spawn(async move {
+            // Children that never read stdin close it early; that is not an error
+            let _ = pipe.write_all(&stdin).await;
+        });
+    }
+
+    let stdout = child.stdout.take().context("Failed to open stdout")?;
+    let stderr = child.stderr.take().context("Failed to open stderr")?;
+    let sink = |stream: OutputStream| Sink {
+        node: node.clone(),
+        stream,
+        global,
+        kept: Kept::new(capture.max_bytes),
+        file: spill_dir.as_ref().map(|d| d.join(format!("{}.{}.log", spill_prefix, stream.as_str()))),
+    };
+    let (stdout, stderr) = tokio::try_join!(
+        sink(OutputStream::Stdout).pump(stdout),
+        sink(OutputStream::Stderr).pump(stderr),
+    )?;
+    let status = child.wait().await.context("Failed to wait for process")?;
+
+    Ok(ProcessOutput { status, stdout, stderr })
+}
+
+/// Where the lines of one stream go
+struct Sink<'a> {
+    node: String,
+    stream: OutputStream,
+    global: &'a GlobalMemory,
+    kept: Kept,
+    file: Option<PathBuf>,
+}
+
+impl Sink<'_> {
+    async fn pump(mut self, reader: impl AsyncRead + Unpin) -> Result<Captured> {
+        let mut reader = BufReader::new(reader);
+        let mut spill = match &self.file {
+            Some(path) => Some(tokio::fs::File::create(path).await
+                .with_context(|| format!("Failed to create spill file `{:?}`", path))?),
+            None => None,
+        };
+
+        let mut line = Vec::new();
+        loop {
+            let chunk = reader.fill_buf().await?;
+            if chunk.is_empty() {
+                break;
+            }
+            let (take, complete) = match chunk.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (chunk.len(), false),
+            };
+            line.extend_from_slice(&chunk[..take]);
+            reader.consume(take);
+
+            if complete || line.len() >= MAX_LINE {
+                if let Some(file) = &mut spill {
+                    file.write_all(&line).await?;
+                }
+                self.emit(&line);
+                line.clear();
+            }
+        }
+        if !line.is_empty() {
+            if let Some(file) = &mut spill {
+                file.write_all(&line).await?;
+            }
+            self.emit(&line);
+        }
+        if let Some(mut file) = spill {
+            file.flush().await?;
+        }
+
+        let (text, truncated) = self.kept.finish();
+        Ok(Captured { text, truncated, file: self.file })
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        let line = String::from_utf8_lossy(bytes);
+        let line = line.trim_end_matches(['\n', '\r']);
+        log::info!("[{}] {}: {}", self.node, self.stream.as_str(), line);
+        self.global.events().publish(RunEvent::NodeOutput {
+            node: self.node.clone(),
+            stream: self.stream,
+            line: line.to_string(),
+        });
+        self.kept.push(bytes);
+    }
+}
+
+/// The first and last `max_bytes / 2` bytes of a stream, in whole lines
+/// (except the newest line, which is cut from the front when it alone is too long)
+struct Kept {
+    half: usize,
+    head: Vec<u8>,
+    tail: VecDeque<Vec<u8>>,
+    tail_len: usize,
+    dropped: usize,
+}
+
+impl Kept {
+    fn new(max_bytes: usize) -> Self {
+        Self { half: max_bytes / 2, head: Vec::new(), tail: VecDeque::new(), tail_len: 0, dropped: 0 }
+    }
+
+    fn push(&mut self, line: &[u8]) {
+        if self.tail.is_empty() && self.head.len() + line.len() <= self.half {
+            self.head.extend_from_slice(line);
+            return;
+        }
+        self.tail.push_back(line.to_vec());
+        self.tail_len += line.len();
+        while self.tail_len > self.half && self.tail.len() > 1 {
+            let Some(dropped) = self.tail.pop_front() else { break };
+            self.tail_len -= dropped.len();
+            self.dropped += dropped.len();
+        }
+        // Always keep the newest chunk, trimmed from the front if it alone is too big
+        if self.tail_len > self.half {
+            let newest = &mut self.tail[0];
+            let mut cut = self.tail_len - self.half;
+            // Don't split a UTF-8 character
+            while cut < newest.len() && (newest[cut] & 0xC0) == 0x80 {
+                cut += 1;
+            }
+            newest.drain(..cut);
+            self.tail_len -= cut;
+            self.dropped += cut;
+        }
+    }
+
+    fn finish(self) -> (String, usize) {
+        let mut bytes = self.head;
+        if self.dropped > 0 {
+            if !bytes.ends_with(b"\n") && !bytes.is_empty() {
+                bytes.push(b'\n');
+            }
+            bytes.extend_from_slice(format!("[... {} bytes truncated ...]\n", self.dropped).as_bytes());
+        }
+        for line in self.tail {
+            bytes.extend_from_slice(&line);
+        }
+        (String::from_utf8_lossy(&bytes).to_string(), self.dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keep(max_bytes: usize, lines: &[&str]) -> (String, usize) {
+        let mut kept = Kept::new(max_bytes);
+        for line in lines {
+            kept.push(line.as_bytes());
+        }
+        kept.finish()
+    }
+
+    #[test]
+    fn short_output_is_kept_whole() {
+        assert_eq!(keep(100, &["a\n", "b\n"]), ("a\nb\n".to_string(), 0));
+    }
+
+    #[test]
+    fn keeps_head_and_tail_lines() {
+        let (text, dropped) = keep(8, &["11\n", "22\n", "33\n", "44\n", "55\n"]);
+        assert_eq!(dropped, 9);
+        assert_eq!(text, "11\n[... 9 bytes truncated ...]\n55\n");
+    }
+
+    #[test]
+    fn newest_oversized_line_is_trimmed_from_the_front() {
+        let (text, dropped) = keep(8, &["a\n", "0123456789\n"]);
+        assert_eq!(dropped, 7);
+        assert_eq!(text, "a\n[... 7 bytes truncated ...]\n789\n");
+    }
+
+    #[test]
+    fn trimming_does_not_split_utf8() {
+        // "éé\n" is 5 bytes: keeping 3 cuts between the characters
+        assert_eq!(keep(6, &["éé\n"]), ("[... 2 bytes truncated ...]\né\n".to_string(), 2));
+        // keeping 2 would start inside the second "é", so it is dropped whole
+        assert_eq!(keep(4, &["éé\n"]), ("[... 4 bytes truncated ...]\n\n".to_string(), 4));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::nodes::{
+    AssignExecutor, DelayExecutor, FileExecutor, HttpExecutor, InputExecutor, JoinExecutor,
+    LlmExecutor, LoopExecutor, McpExecutor, NodeExecutor, ScriptExecutor, ShellExecutor,
+    SwitchExecutor, TransformExecutor, WorkflowExecutor,
+};
+use crate::schema::Node;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, Weak};
+
+/// Maps node `type`s to their executors.
+///
+/// Cloning shares the same table, so a type registered after an engine is
+/// built is still visible to it (and to loop/workflow sub-engines).
+#[derive(Clone)]
+pub struct ExecutorRegistry {
+    inner: Arc<RwLock<Table>>,
+}
+
+#[derive(Default)]
+struct Table {
+    executors: HashMap<String, Arc<dyn NodeExecutor>>,
+    /// Registration order, for listings and the JSON Schema
+    order: Vec<String>,
+}
+
+/// Non-owning handle held by executors that start sub-engines
+#[derive(Clone)]
+pub struct WeakRegistry(Weak<RwLock<Table>>);
+
+impl WeakRegistry {
+    pub fn upgrade(&self) -> Result<ExecutorRegistry> {
+        self.0
+            .upgrade()
+            .map(|inner| ExecutorRegistry { inner })
+            .ok_or_else(|| anyhow::anyhow!("Executor registry was dropped"))
+    }
+}
+
+impl Default for ExecutorRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExecutorRegistry {
+    /// A registry with every built-in node type
+    pub fn new() -> Self {
+        let registry = Self::empty();
+        registry.register("shell", ShellExecutor);
+        registry.register("http", HttpExecutor);
+        registry.register("delay", DelayExecutor);
+        registry.register("switch", SwitchExecutor);
+        registry.register("join", JoinExecutor);
+        registry.register("script", ScriptExecutor::default());
+        registry.register("llm", LlmExecutor);
+        registry.register("transform", TransformExecutor);
+        registry.register("file", FileExecutor);
+        registry.register("input", InputExecutor);
+        registry.register("loop", LoopExecutor::new(&registry));
+        registry.register("assign", AssignExecutor);
+        registry.register("mcp", McpExecutor);
+        registry.register("workflow", WorkflowExecutor::new(&registry));
+        registry
+    }
+
+    /// A registry without any node types
+    pub fn empty() -> Self {
+        Self { inner: Arc::new(RwLock::new(Table::default())) }
+    }
+
+    /// Add a node type, replacing any executor already registered for it
+    pub fn register(&self, node_type: impl Into<String>, executor: impl NodeExecutor + 'static) {
+        self.register_arc(node_type, Arc::new(executor));
+    }
+
+    pub fn register_arc(&self, node_type: impl Into<String>, executor: Arc<dyn NodeExecutor>) {
+        let node_type = node_type.into();
+        let mut table = self.inner.write().unwrap();
+        if table.executors.insert(node_type.clone(), executor).is_none() {
+            table.order.push(node_type);
+        }
+    }
+
+    /// Builder-style [`register`](Self::register)
+    pub fn with(self, node_type: impl Into<String>, executor: impl NodeExecutor + 'static) -> Self {
+        self.register(node_type, executor);
+        self
+    }
+
+    pub fn get(&self, node_type: &str) -> Result<Arc<dyn NodeExecutor>> {
+        self.inner
+            .read()
+            .unwrap()
+            .executors
+            .get(node_type)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Unknown node type: {}", node_type))
+    }
+
+    pub fn contains(&self, node_type: &str) -> bool {
+        self.inner.read().unwrap().executors.contains_key(node_type)
+    }
+
+    /// Registered node types, in registration order
+    pub fn types(&self) -> Vec<String> {
+        self.inner.read().unwrap().order.clone()
+    }
+
+    /// Check a node's params with the executor for its type
+    pub fn validate(&self, node: &Node) -> Result<()> {
+        self.get(&node.node_type)?.validate(node)
+    }
+
+    pub fn downgrade(&self) -> WeakRegistry {
+        WeakRegistry(Arc::downgrade(&self.inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{GlobalMemory, NodeMemory, NodeOutput};
+    use crate::Engine;
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Echoes its params back, implementing nothing but `execute`
+    struct Echo;
+
+    #[async_trait]
+    impl NodeExecutor for Echo {
+        async fn execute(&self, node: &Node, _global: &GlobalMemory, _nodes: &NodeMemory) -> Result<NodeOutput> {
+            Ok(NodeOutput { status: "success".to_string(), output: node.params.clone() })
+        }
+    }
+
+    fn node(value: Value) -> Node {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn execute_only_executor_accepts_any_params() {
+        let registry = ExecutorRegistry::new().with("echo", Echo);
+        let echo = node(json!({"id": "e", "type": "echo", "params": {"anything": [1, 2]}}));
+        registry.validate(&echo).unwrap();
+
+        let schema = crate::schema::json_schema(&registry);
+        let rule = schema["$defs"]["Node"]["allOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|rule| rule["if"]["properties"]["type"]["const"] == "echo")
+            .unwrap();
+        assert_eq!(rule["then"]["properties"]["params"], json!({}));
+
+        let workflow = serde_json::from_value(json!({
+            "name": "echo", "version": "2.0", "nodes": [echo],
+        }))
+        .unwrap();
+        let result = Engine::new(workflow).unwrap().with_executors(registry).run().await.unwrap();
+        assert_eq!(result.node_outputs["e"], json!({"anything": [1, 2]}));
+    }
+
+    #[test]
+    fn built_in_params_are_typed() {
+        let registry = ExecutorRegistry::new();
+        let typo = node(json!({"id": "a", "type": "assign", "params": {"assigments": []}}));
+        let err = registry.validate(&typo).unwrap_err();
+        assert!(format!("{:#}", err).contains("assigments"), "{:#}", err);
+
+        let unknown = node(json!({"id": "x", "type": "nope"}));
+        assert!(registry.validate(&unknown).unwrap_err().to_string().contains("Unknown node type"));
+    }
+
+    /// Counts its calls
+    struct Counter(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl NodeExecutor for Counter {
+        async fn execute(&self, _node: &Node, _global: &GlobalMemory, _nodes: &NodeMemory) -> Result<NodeOutput> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(NodeOutput { status: "success".to_string(), output: Value::Null })
+        }
+    }
+
+    #[tokio::test]
+    async fn types_registered_after_build_reach_loop_steps() {
+        let registry = ExecutorRegistry::new();
+        let workflow = serde_json::from_value(json!({
+            "name": "shared",
+            "version": "2.0",
+            "nodes": [
+                {"id": "each", "type": "loop", "params": {
+                    "items": [1, 2, 3],
+                    "steps": [{"id": "count", "type": "counter"}],
+                }},
+            ],
+        }))
+        .unwrap();
+        let engine = Engine::builder().workflow(workflow).executor_registry(registry.clone()).build().unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        registry.register("counter", Counter(calls.clone()));
+        assert!(engine.executors().contains("counter"));
+
+        engine.run().await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn registering_again_replaces_without_reordering() {
+        let registry = ExecutorRegistry::empty().with("a", Echo).with("b", Echo);
+        registry.register("a", Counter(Default::default()));
+        assert_eq!(registry.types(), vec!["a", "b"]);
+        assert!(registry.get("c").is_err());
+    }
+}
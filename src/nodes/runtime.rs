@@ -0,0 +1,359 @@
+//! Interpreters available to `script` nodes.
+//!
+//! A runtime is resolved in layers: the built-in table, then the engine's
+//! `--runtimes` file, then the node's own `runtime:` (which the loader fills
+//! from the workflow's top-level `runtimes:`).
+//!
+//! Scripts that declare `requirements:` run in an environment built once per
+//! distinct set of requirements and kept in the engine's environment cache.
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Runtime settings; every field is optional so layers can override a few of them
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeSpec {
+    /// Interpreter program
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Arguments placed before the script path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<String>>,
+    /// Extension of the temp script file, without the dot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extension: Option<String>,
+    /// Virtualenv to run Python from (`<venv>/bin/python`); relative to the workflow file.
+    /// Only for the `python` language
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub venv: Option<String>,
+    /// How `entrypoint` functions are called; none means `entrypoint` is unsupported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub harness: Option<Harness>,
+    /// Local package mirror for `requirements:`: a directory of wheels (pip
+    /// `--find-links`) or an npm cache (`npm --offline --cache`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<String>,
+}
+
+impl RuntimeSpec {
+    /// Layer `other` on top of `self`
+    pub fn merge(&mut self, other: &RuntimeSpec) {
+        if other.command.is_some() {
+            self.command = other.command.clone();
+        }
+        if other.args.is_some() {
+            self.args = other.args.clone();
+        }
+        if other.extension.is_some() {
+            self.extension = other.extension.clone();
+        }
+        if other.venv.is_some() {
+            self.venv = other.venv.clone();
+        }
+        if other.harness.is_some() {
+            self.harness = other.harness;
+        }
+        if other.mirror.is_some() {
+            self.mirror = other.mirror.clone();
+        }
+    }
+}
+
+/// Code appended to a script to call its `entrypoint` with the inputs
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Harness {
+    Python,
+    Node,
+    Deno,
+    Ruby,
+}
+
+impl Harness {
+    /// Source appended to the script; `{entrypoint}` is the function name
+    pub fn source(&self) -> &'static str {
+        match self {
+            Harness::Python => PYTHON_HARNESS,
+            Harness::Node => NODE_HARNESS,
+            Harness::Deno => DENO_HARNESS,
+            Harness::Ruby => RUBY_HARNESS,
+        }
+    }
+
+    /// Interpreter flags granting access to the script's directory and result
+    /// file (and nothing else), for interpreters that sandbox scripts
+    pub fn permissions(&self, script_dir: &Path, result_file: &Path) -> Vec<String> {
+        match self {
+            Harness::Deno => vec![
+                format!("--allow-read={}", script_dir.display()),
+                format!("--allow-write={}", result_file.display()),
+                "--allow-env=WORKFLOW_INPUTS_FILE,WORKFLOW_RESULT_FILE".to_string(),
+            ],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Whether `name` can be spliced into a harness as a function name
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+const PYTHON_HARNESS: &str = r#"
+
+if __name__ == "__main__":
+    import json as _wf_json, os as _wf_os
+    with open(_wf_os.environ["WORKFLOW_INPUTS_FILE"]) as _wf_file:
+        _wf_inputs = _wf_json.load(_wf_file)
+    _wf_result = {entrypoint}(**_wf_inputs)
+    with open(_wf_os.environ["WORKFLOW_RESULT_FILE"], "w") as _wf_file:
+        _wf_json.dump(_wf_result, _wf_file)
+"#;
+
+const NODE_HARNESS: &str = r#"
+;(async () => {
+  const _wfFs = require("fs");
+  const _wfInputs = JSON.parse(_wfFs.readFileSync(process.env.WORKFLOW_INPUTS_FILE, "utf8"));
+  const _wfResult = await {entrypoint}(_wfInputs);
+  _wfFs.writeFileSync(process.env.WORKFLOW_RESULT_FILE, JSON.stringify(_wfResult ?? null));
+})().catch((e) => { console.error(e); process.exit(1); });
+"#;
+
+const DENO_HARNESS: &str = r#"
+const _wfInputs = JSON.parse(Deno.readTextFileSync(Deno.env.get("WORKFLOW_INPUTS_FILE")!));
+const _wfResult = await {entrypoint}(_wfInputs);
+Deno.writeTextFileSync(Deno.env.get("WORKFLOW_RESULT_FILE")!, JSON.stringify(_wfResult ?? null));
+"#;
+
+const RUBY_HARNESS: &str = r#"
+if __FILE__ == $0
+  require "json"
+  _wf_inputs = JSON.parse(File.read(ENV["WORKFLOW_INPUTS_FILE"]), symbolize_names: true)
+  _wf_result = {entrypoint}(**_wf_inputs)
+  File.write(ENV["WORKFLOW_RESULT_FILE"], JSON.generate(_wf_result))
+end
+"#;
+
+/// A fully resolved runtime
+#[derive(Debug, Clone)]
+pub struct Runtime {
+    pub command: String,
+    pub args: Vec<String>,
+    pub extension: String,
+    pub harness: Option<Harness>,
+    pub mirror: Option<PathBuf>,
+    /// Extra environment variables for the interpreter
+    pub env: Vec<(String, String)>,
+}
+
+/// Runtimes by language name
+#[derive(Debug, Clone)]
+pub struct RuntimeTable {
+    runtimes: HashMap<String, RuntimeSpec>,
+    /// Where environments built for `requirements:` are kept
+    env_cache: PathBuf,
+}
+
+impl Default for RuntimeTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Other names accepted for built-in languages
+const ALIASES: &[(&str, &str)] = &[
+    ("python3", "python"),
+    ("js", "javascript"),
+    ("node", "javascript"),
+    ("sh", "bash"),
+];
+
+/// Name a language is configured under, resolving aliases
+pub fn canonical_language(language: &str) -> &str {
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == language)
+        .map(|(_, name)| *name)
+        .unwrap_or(language)
+}
+
+fn spec(command: &str, args: &[&str], extension: &str, harness: Option<Harness>) -> RuntimeSpec {
+    RuntimeSpec {
+        command: Some(command.to_string()),
+        args: Some(args.iter().map(|a| a.to_string()).collect()),
+        extension: Some(extension.to_string()),
+        venv: None,
+        harness,
+        mirror: None,
+    }
+}
+
+impl RuntimeTable {
+    /// Runtimes shipped with the engine
+    pub fn builtin() -> Self {
+        let runtimes = HashMap::from([
+            ("python".to_string(), spec("python3", &[], "py", Some(Harness::Python))),
+            ("javascript".to_string(), spec("node", &[], "js", Some(Harness::Node))),
+            (
+                "deno".to_string(),
+                spec("deno", &["run", "--quiet"], "ts", Some(Harness::Deno)),
+            ),
+            ("bash".to_string(), spec("bash", &[], "sh", None)),
+            ("ruby".to_string(), spec("ruby", &[], "rb", Some(Harness::Ruby))),
+            ("lua".to_string(), spec("lua", &[], "lua", None)),
+        ]);
+        Self {
+            runtimes,
+            env_cache: std::env::temp_dir().join("workflow-engine-envs"),
+        }
+    }
+
+    /// Keep `requirements:` environments in `dir`; share it between workers to build each once
+    pub fn set_env_cache(&mut self, dir: impl Into<PathBuf>) {
+        self.env_cache = dir.into();
+    }
+
+    pub fn env_cache(&self) -> &Path {
+        &self.env_cache
+    }
+
+    /// Layer a runtimes file (language name -> settings) over this table
+    pub fn load_overrides(&mut self, path: &Path) -> Result<()> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read runtimes file `{:?}`", path))?;
+        let overrides: HashMap<String, RuntimeSpec> = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse runtimes file `{:?}`", path))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+        for (language, mut spec) in overrides {
+            if let Some(venv) = &spec.venv {
+                spec.venv = Some(base_dir.join(venv).to_string_lossy().to_string());
+            }
+            if let Some(mirror) = &spec.mirror {
+                spec.mirror = Some(base_dir.join(mirror).to_string_lossy().to_string());
+            }
+            self.runtimes
+                .entry(canonical_language(&language).to_string())
+                .or_default()
+                .merge(&spec);
+        }
+        Ok(())
+    }
+
+    /// Resolve `language`, applying the node's own `runtime:` on top
+    pub fn resolve(&self, language: &str, node_spec: Option<&RuntimeSpec>) -> Result<Runtime> {
+        let mut spec = self
+            .runtimes
+            .get(canonical_language(language))
+            .cloned()
+            .unwrap_or_default();
+        if let Some(node_spec) = node_spec {
+            spec.merge(node_spec);
+        }
+
+        if spec.venv.is_some() && canonical_language(language) != "python" {
+            anyhow::bail!("'venv' is only supported for python, not '{}'", language);
+        }
+        let command = match (&spec.venv, spec.command) {
+            (Some(venv), _) => Path::new(venv).join("bin").join("python").to_string_lossy().to_string(),
+            (None, Some(command)) => command,
+            (None, None) => anyhow::bail!(
+                "Unsupported script language: {} (add it under `runtimes:` with a `command`)",
+                language
+            ),
+        };
+        Ok(Runtime {
+            command,
+            args: spec.args.unwrap_or_default(),
+            extension: spec.extension.unwrap_or_else(|| "txt".to_string()),
+            harness: spec.harness,
+            mirror: spec.mirror.map(PathBuf::from),
+            env: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_resolve_to_built_in_runtimes() {
+        let table = RuntimeTable::builtin();
+        for (alias, command) in [("python3", "python3"), ("js", "node"), ("node", "node"), ("sh", "bash")] {
+            assert_eq!(table.resolve(alias, None).unwrap().command, command);
+        }
+        let deno = table.resolve("deno", None).unwrap();
+        assert_eq!(deno.args, vec!["run", "--quiet"]);
+        assert_eq!(deno.harness, Some(Harness::Deno));
+        assert!(table.resolve("lua", None).unwrap().harness.is_none());
+
+        let err = table.resolve("cobol", None).unwrap_err();
+        assert!(err.to_string().contains("Unsupported script language: cobol"), "{}", err);
+    }
+
+    #[test]
+    fn file_and_node_layers_override_single_fields() {
+        let dir = std::env::temp_dir().join(format!("workflow-engine-runtimes-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("runtimes.yaml");
+        std::fs::write(&path, "python3: {command: /opt/python/bin/python3}\nphp: {command: php, extension: php}\n").unwrap();
+
+        let mut table = RuntimeTable::builtin();
+        table.load_overrides(&path).unwrap();
+
+        let python = table.resolve("python", None).unwrap();
+        assert_eq!(python.command, "/opt/python/bin/python3");
+        assert_eq!(python.extension, "py");
+        assert_eq!(python.harness, Some(Harness::Python));
+
+        let php = table.resolve("php", None).unwrap();
+        assert_eq!((php.command.as_str(), php.extension.as_str()), ("php", "php"));
+        assert!(php.harness.is_none());
+
+        let node = RuntimeSpec { args: Some(vec!["-X".to_string()]), ..RuntimeSpec::default() };
+        let python = table.resolve("python", Some(&node)).unwrap();
+        assert_eq!(python.command, "/opt/python/bin/python3");
+        assert_eq!(python.args, vec!["-X"]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn venv_is_only_for_python() {
+        let table = RuntimeTable::builtin();
+        let venv = RuntimeSpec { venv: Some("/srv/venv".to_string()), ..RuntimeSpec::default() };
+        assert_eq!(table.resolve("python3", Some(&venv)).unwrap().command, "/srv/venv/bin/python");
+        let err = table.resolve("ruby", Some(&venv)).unwrap_err();
+        assert!(err.to_string().contains("only supported for python"), "{}", err);
+    }
+
+    #[test]
+    fn entrypoints_must_be_plain_names() {
+        for name in ["main", "_run", "handler2"] {
+            assert!(is_identifier(name), "{}", name);
+        }
+        for name in ["", "2main", "main()", "os.system", "a b", "x;y"] {
+            assert!(!is_identifier(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn deno_is_granted_only_the_script_files() {
+        let permissions = Harness::Deno.permissions(Path::new("/tmp/s"), Path::new("/tmp/s/result.json"));
+        assert_eq!(
+            permissions,
+            vec![
+                "--allow-read=/tmp/s",
+                "--allow-write=/tmp/s/result.json",
+                "--allow-env=WORKFLOW_INPUTS_FILE,WORKFLOW_RESULT_FILE",
+            ]
+        );
+        assert!(Harness::Python.permissions(Path::new("/tmp/s"), Path::new("/tmp/r")).is_empty());
+    }
+}
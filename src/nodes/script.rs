@@ -1,14 +1,150 @@
-use crate::nodes::NodeExecutor;
+use crate::nodes::process::{self, Capture, ProcessOutput};
+use crate::nodes::{environment, params_schema, parse_params, wasm, NodeExecutor};
+use crate::nodes::runtime::{self, RuntimeSpec, RuntimeTable};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::PathBuf;
 use std::process::Stdio;
 use tokio::process::Command;
-use uuid;
 
-pub struct ScriptExecutor;
+/// Env var naming the JSON file that holds the node's `inputs`
+const INPUTS_FILE_ENV: &str = "WORKFLOW_INPUTS_FILE";
+
+/// Env var naming the file a script may write its JSON result to
+const RESULT_FILE_ENV: &str = "WORKFLOW_RESULT_FILE";
+
+/// Runs `script` nodes with the interpreters in its runtime table
+#[derive(Default)]
+pub struct ScriptExecutor {
+    runtimes: RuntimeTable,
+}
+
+impl ScriptExecutor {
+    pub fn new(runtimes: RuntimeTable) -> Self {
+        Self { runtimes }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptParams {
+    /// Script source (templated); for `wasm`, readable by the module at `/script/main`
+    #[serde(default)]
+    pub script: Option<String>,
+    /// A runtime name (`python`, `javascript`, `deno`, `bash`, `ruby`, `lua`, or one
+    /// configured under `runtimes:`), or `wasm`
+    #[serde(default = "default_language")]
+    pub language: String,
+    /// Overrides for this node's runtime (interpreter, args, extension, venv, mirror)
+    #[serde(default)]
+    pub runtime: Option<RuntimeSpec>,
+    /// Packages to install (pip or npm specifiers) into a cached environment
+    /// for this script; taken from the runtime's `mirror` when one is set
+    #[serde(default)]
+    pub requirements: Vec<String>,
+    /// Values passed to the script as JSON on stdin and in `$WORKFLOW_INPUTS_FILE`;
+    /// strings are templated and single expressions keep their type
+    #[serde(default)]
+    pub inputs: Option<Value>,
+    /// Function called with the inputs (`main(**inputs)` in Python and Ruby,
+    /// `main(inputs)` in JavaScript); its return value becomes `output.result`
+    #[serde(default)]
+    pub entrypoint: Option<String>,
+    /// Where `output.result` is read from
+    #[serde(default)]
+    pub result: ResultSource,
+    /// How much output is kept in `output.stdout` / `output.stderr`
+    #[serde(default)]
+    pub capture: Capture,
+    /// `wasm` only: WASI module to run, a `.wasm` or `.wat` file (templated)
+    #[serde(default)]
+    pub module: Option<String>,
+    /// `wasm` only: arguments after the program name (templated);
+    /// defaults to `/script/main` when a script is given
+    #[serde(default)]
+    pub args: Option<Vec<String>>,
+    /// `wasm` only: memory/time limits and granted directories
+    #[serde(default)]
+    pub sandbox: wasm::Sandbox,
+}
+
+fn default_language() -> String {
+    "python".to_string()
+}
+
+/// Where a script's structured result comes from
+#[derive(Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultSource {
+    /// `$WORKFLOW_RESULT_FILE` if written, else stdout as JSON, else its last line as JSON
+    #[default]
+    Auto,
+    /// Only `$WORKFLOW_RESULT_FILE`
+    File,
+    /// The whole of stdout as JSON
+    Stdout,
+    /// The last non-empty stdout line as JSON
+    LastLine,
+    /// No `output.result`
+    None,
+}
+
+/// Files shared with a script process: its source, inputs and result
+struct ScriptIo {
+    dir: PathBuf,
+    inputs: Vec<u8>,
+}
+
+impl ScriptIo {
+    async fn new(inputs: &Value) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("workflow_script_{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await
+            .context("Failed to create script directory")?;
+        let inputs = serde_json::to_vec(inputs)?;
+        tokio::fs::write(dir.join("inputs.json"), &inputs).await
+            .context("Failed to write script inputs")?;
+        Ok(Self { dir, inputs })
+    }
+
+    async fn write_script(&self, name: &str, source: &str) -> Result<PathBuf> {
+        let path = self.dir.join(name);
+        tokio::fs::write(&path, source).await
+            .context("Failed to write script to temp file")?;
+        Ok(path)
+    }
+
+    fn result_file(&self) -> PathBuf {
+        self.dir.join("result.json")
+    }
+
+    /// Run `command` with the inputs on stdin and the I/O env vars set
+    async fn run(
+        &self,
+        mut command: Command,
+        global: &GlobalMemory,
+        capture: &Capture,
+        template: &TemplateEngine,
+    ) -> Result<ProcessOutput> {
+        let child = command
+            .env(INPUTS_FILE_ENV, self.dir.join("inputs.json"))
+            .env(RESULT_FILE_ENV, self.result_file())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        process::run(child, Some(self.inputs.clone()), global, capture, template).await
+    }
+
+    async fn cleanup(self) {
+        let _ = tokio::fs::remove_dir_all(&self.dir).await;
+    }
+}
 
 #[async_trait]
 impl NodeExecutor for ScriptExecutor {
@@ -18,96 +154,258 @@ impl NodeExecutor for ScriptExecutor {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
+        let params: ScriptParams = parse_params(node)?;
         let template = TemplateEngine::new(global.clone(), nodes.clone());
-        
-        let script = node.params
-            .get("script")
-            .and_then(|v| v.as_str())
-            .context("Script node requires 'script' parameter")?;
-
-        let language = node.params
-            .get("language")
-            .and_then(|v| v.as_str())
-            .unwrap_or("python");
-
-        let rendered_script = template.render(script)?;
-        
-        log::info!("Executing {} script", language);
-
-        let output = match language {
-            "python" | "python3" => execute_python(&rendered_script).await?,
-            "javascript" | "js" | "node" => execute_javascript(&rendered_script).await?,
-            _ => anyhow::bail!("Unsupported script language: {}", language),
+        let language = params.language.as_str();
+
+        let inputs = match &params.inputs {
+            Some(inputs) => template.render_value(inputs)?,
+            None => Value::Object(Default::default()),
         };
 
-        Ok(output)
+        if language == "wasm" {
+            return execute_wasm(params, inputs, &template).await;
+        }
+
+        let mut runtime = self.runtimes.resolve(language, params.runtime.as_ref())?;
+        if !params.requirements.is_empty() {
+            environment::prepare(&mut runtime, &params.requirements, self.runtimes.env_cache()).await?;
+        }
+        let mut source = template.render(params.script.as_deref().unwrap_or_default())?;
+        if let Some(entrypoint) = &params.entrypoint {
+            check_entrypoint(entrypoint)?;
+            let harness = runtime.harness
+                .with_context(|| format!("Language '{}' does not support 'entrypoint'", language))?;
+            source.push('\n');
+            source.push_str(&harness.source().replace("{entrypoint}", entrypoint));
+        }
+
+        log::info!("Executing {} script with {}", language, runtime.command);
+
+        let io = ScriptIo::new(&inputs).await?;
+        let output = async {
+            let path = io.write_script(&format!("main.{}", runtime.extension), &source).await?;
+            let permissions = runtime
+                .harness
+                .map(|harness| harness.permissions(&io.dir, &io.result_file()))
+                .unwrap_or_default();
+            let mut command = Command::new(&runtime.command);
+            command.args(&runtime.args).args(permissions).arg(path).envs(runtime.env.iter().map(|(k, v)| (k, v)));
+            io.run(command, global, &params.capture, &template).await.with_context(|| {
+                format!("Failed to execute {} script. Is {} installed?", language, runtime.command)
+            })
+        }
+        .await;
+        let result_file = tokio::fs::read_to_string(io.result_file()).await.ok();
+        io.cleanup().await;
+
+        let output = output?;
+        let success = output.status.success();
+
+        let mut result = output.to_output(success);
+        if let Some(value) = parse_result(params.result, result_file.as_deref(), &output.stdout.text)? {
+            result["result"] = value;
+        }
+
+        Ok(NodeOutput {
+            status: if success { "success".to_string() } else { "failed".to_string() },
+            output: result,
+        })
+    }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<ScriptParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        let params: ScriptParams = parse_params(node)?;
+        if params.language == "wasm" {
+            if params.module.is_none() {
+                anyhow::bail!("Script node '{}' with language 'wasm' requires 'module'", node.id);
+            }
+            if params.entrypoint.is_some() || params.result == ResultSource::File || !params.requirements.is_empty() {
+                anyhow::bail!(
+                    "Script node '{}': 'entrypoint', 'requirements' and 'result: file' are not supported for wasm",
+                    node.id
+                );
+            }
+        } else {
+            if params.script.is_none() {
+                anyhow::bail!("Script node '{}' requires 'script'", node.id);
+            }
+            if let Some(key) = ["module", "args", "sandbox"].into_iter().find(|key| node.params.get(key).is_some()) {
+                anyhow::bail!("Script node '{}': '{}' is only supported for wasm", node.id, key);
+            }
+            if let Some(entrypoint) = &params.entrypoint {
+                check_entrypoint(entrypoint).with_context(|| format!("Script node '{}'", node.id))?;
+            }
+            let runtime = self.runtimes.resolve(&params.language, params.runtime.as_ref())?;
+            if params.entrypoint.is_some() && runtime.harness.is_none() {
+                anyhow::bail!("Script node '{}': language '{}' does not support 'entrypoint'", node.id, params.language);
+            }
+            if !params.requirements.is_empty() && !environment::supports_requirements(&runtime) {
+                anyhow::bail!("Script node '{}': language '{}' does not support 'requirements'", node.id, params.language);
+            }
+        }
+        Ok(())
     }
 }
 
-async fn execute_python(script: &str) -> Result<NodeOutput> {
-    // Create a temporary file for the script
-    let temp_file = std::env::temp_dir().join(format!("workflow_script_{}.py", uuid::Uuid::new_v4()));
-    tokio::fs::write(&temp_file, script).await
-        .context("Failed to write Python script to temp file")?;
-
-    let output = Command::new("python3")
-        .arg(&temp_file)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .context("Failed to execute Python script. Is python3 installed?")?;
-
-    // Clean up temp file
-    let _ = tokio::fs::remove_file(&temp_file).await;
-
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-    let success = output.status.success();
-
-    let result = serde_json::json!({
-        "stdout": stdout.trim(),
-        "stderr": stderr.trim(),
-        "exit_code": output.status.code().unwrap_or(-1),
-        "success": success
-    });
+/// The entrypoint is spliced into the harness source, so it must be a plain function name
+fn check_entrypoint(entrypoint: &str) -> Result<()> {
+    if !runtime::is_identifier(entrypoint) {
+        anyhow::bail!("'entrypoint' must be a function name matching ^[A-Za-z_][A-Za-z0-9_]*$, got '{}'", entrypoint);
+    }
+    Ok(())
+}
 
-    Ok(NodeOutput {
-        status: if success { "success".to_string() } else { "failed".to_string() },
-        output: result,
+/// Structured result of a script according to `source`
+fn parse_result(source: ResultSource, result_file: Option<&str>, stdout: &str) -> Result<Option<Value>> {
+    let from_file = || -> Result<Option<Value>> {
+        result_file
+            .filter(|content| !content.trim().is_empty())
+            .map(|content| serde_json::from_str(content).context("Script result file is not valid JSON"))
+            .transpose()
+    };
+    let whole = || serde_json::from_str::<Value>(stdout.trim()).ok();
+    let last_line = || {
+        stdout
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .and_then(|line| serde_json::from_str::<Value>(line.trim()).ok())
+    };
+
+    Ok(match source {
+        ResultSource::Auto => match from_file()? {
+            Some(value) => Some(value),
+            None => whole().or_else(last_line),
+        },
+        ResultSource::File => from_file()?,
+        ResultSource::Stdout => whole(),
+        ResultSource::LastLine => last_line(),
+        ResultSource::None => None,
     })
 }
 
-async fn execute_javascript(script: &str) -> Result<NodeOutput> {
-    // Create a temporary file for the script
-    let temp_file = std::env::temp_dir().join(format!("workflow_script_{}.js", uuid::Uuid::new_v4()));
-    tokio::fs::write(&temp_file, script).await
-        .context("Failed to write JavaScript script to temp file")?;
-
-    let output = Command::new("node")
-        .arg(&temp_file)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .context("Failed to execute JavaScript script. Is node installed?")?;
-
-    // Clean up temp file
-    let _ = tokio::fs::remove_file(&temp_file).await;
-
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-    let success = output.status.success();
-
-    let result = serde_json::json!({
-        "stdout": stdout.trim(),
-        "stderr": stderr.trim(),
-        "exit_code": output.status.code().unwrap_or(-1),
+async fn execute_wasm(params: ScriptParams, stdin: Value, template: &TemplateEngine) -> Result<NodeOutput> {
+    let module = params.module.as_deref().context("Language 'wasm' requires 'module'")?;
+    let module = PathBuf::from(template.render(module)?);
+    let script = params.script.as_deref().map(|s| template.render(s)).transpose()?;
+    let args = match params.args {
+        Some(args) => args.iter().map(|a| template.render(a)).collect::<Result<Vec<_>>>()?,
+        None if script.is_some() => vec!["/script/main".to_string()],
+        None => Vec::new(),
+    };
+
+    log::info!("Executing wasm module {:?}", module);
+
+    let output = wasm::run(wasm::WasmRun { module, args, script, stdin, sandbox: params.sandbox }).await?;
+
+    let stdout = output.stdout.trim();
+    let success = output.exit_code == 0 && output.error.is_none();
+    let mut result = serde_json::json!({
+        "stdout": stdout,
+        "stderr": output.stderr.trim(),
+        "exit_code": output.exit_code,
         "success": success
     });
+    if let Some(value) = parse_result(params.result, None, stdout)? {
+        result["result"] = value;
+    }
+    if let Some(error) = output.error {
+        result["error"] = Value::String(error);
+    }
 
     Ok(NodeOutput {
         status: if success { "success".to_string() } else { "failed".to_string() },
         output: result,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(params: Value) -> Node {
+        serde_json::from_value(json!({"id": "s", "type": "script", "params": params})).unwrap()
+    }
+
+    fn validate(params: Value) -> Result<()> {
+        ScriptExecutor::default().validate(&node(params))
+    }
+
+    #[test]
+    fn wasm_only_params_are_rejected_for_other_languages() {
+        let err = validate(json!({"language": "python", "script": "print(1)", "sandbox": {"memory_mb": 8}})).unwrap_err();
+        assert!(err.to_string().contains("'sandbox' is only supported for wasm"), "{}", err);
+        assert!(validate(json!({"script": "print(1)", "module": "m.wat"})).is_err());
+
+        assert!(validate(json!({"language": "wasm"})).unwrap_err().to_string().contains("requires 'module'"));
+        assert!(validate(json!({"language": "wasm", "module": "m.wat", "entrypoint": "main"})).is_err());
+        validate(json!({"language": "wasm", "module": "m.wat", "sandbox": {"timeout_ms": 50}})).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wasm_module_is_found_relative_to_the_workflow_file() {
+        let dir = std::env::temp_dir().join(format!("workflow-engine-script-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("wasm")).unwrap();
+        let echo = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples/wasm/echo.wat");
+        std::fs::copy(echo, dir.join("wasm/echo.wat")).unwrap();
+        std::fs::write(
+            dir.join("flow.yaml"),
+            "name: wasm\nversion: '2.0'\nnodes:\n  - {id: echo, type: script, params: {language: wasm, module: wasm/echo.wat, inputs: {n: 1}}}\n",
+        )
+        .unwrap();
+
+        let workflow = crate::loader::load_workflow(&dir.join("flow.yaml")).unwrap();
+        let result = crate::Engine::new(workflow).unwrap().run().await.unwrap();
+        assert_eq!(result.node_outputs["echo"]["result"], json!({"echo": {"n": 1}}));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    async fn run(params: Value) -> NodeOutput {
+        let global = GlobalMemory::new();
+        global.set("n".to_string(), json!(3)).unwrap();
+        ScriptExecutor::default().execute(&node(params), &global, &NodeMemory::new()).await.unwrap()
+    }
+
+    #[test]
+    fn result_sources() {
+        let stdout = "progress\n{\"last\": true}\n";
+        let file = Some(r#"{"file": 1}"#);
+        assert_eq!(parse_result(ResultSource::Auto, file, stdout).unwrap(), Some(json!({"file": 1})));
+        assert_eq!(parse_result(ResultSource::Auto, Some(" "), stdout).unwrap(), Some(json!({"last": true})));
+        assert_eq!(parse_result(ResultSource::Auto, None, "[1, 2]\n").unwrap(), Some(json!([1, 2])));
+        assert_eq!(parse_result(ResultSource::File, None, stdout).unwrap(), None);
+        assert_eq!(parse_result(ResultSource::Stdout, None, stdout).unwrap(), None);
+        assert_eq!(parse_result(ResultSource::LastLine, None, stdout).unwrap(), Some(json!({"last": true})));
+        assert_eq!(parse_result(ResultSource::None, file, stdout).unwrap(), None);
+        assert!(parse_result(ResultSource::File, Some("{"), stdout).is_err());
+    }
+
+    #[tokio::test]
+    async fn inputs_keep_their_types_and_entrypoint_returns_the_result() {
+        let output = run(json!({
+            "language": "python",
+            "inputs": {"n": "{{ global.n }}", "label": "n={{ global.n }}"},
+            "entrypoint": "main",
+            "script": "def main(n, label):\n    return {'double': n * 2, 'label': label}\n",
+        }))
+        .await;
+        assert_eq!(output.status, "success");
+        assert_eq!(output.output["result"], json!({"double": 6, "label": "n=3"}));
+    }
+
+    #[tokio::test]
+    async fn result_file_wins_over_stdout() {
+        let output = run(json!({
+            "language": "bash",
+            "inputs": {"n": "{{ global.n }}"},
+            "script": "echo '{\"from\": \"stdout\"}'\ncat \"$WORKFLOW_INPUTS_FILE\" > \"$WORKFLOW_RESULT_FILE\"\n",
+        }))
+        .await;
+        assert_eq!(output.output["result"], json!({"n": 3}));
+    }
+}
@@ -1,14 +1,104 @@
-use crate::nodes::NodeExecutor;
+use crate::nodes::process::{self, Capture, Captured};
+use crate::nodes::{params_schema, parse_params, NodeExecutor};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::process::Stdio;
 use tokio::process::Command;
 
 pub struct ShellExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ShellParams {
+    /// Command run with `<shell> -c` (templated)
+    pub command: String,
+    /// `sh` (default), `bash`, any program taking `-c`, or a full argv
+    /// prefix such as `["pwsh", "-Command"]` that the command is appended to
+    #[serde(default)]
+    pub shell: Option<ShellProgram>,
+    /// Extra environment variables (values templated); the engine's environment is inherited
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Working directory (templated); the engine's by default
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Written to the command's stdin: strings are templated and sent as-is,
+    /// other values are templated and sent as JSON
+    #[serde(default)]
+    pub stdin: Option<Value>,
+    /// Exit codes that count as success
+    #[serde(default = "default_exit_codes")]
+    pub allowed_exit_codes: Vec<i32>,
+    /// Fail the node (stopping the workflow) on any other exit code, instead
+    /// of recording `status: failed`
+    #[serde(default)]
+    pub fail_on_error: bool,
+    /// Parse stdout into `output.result`; failures go to `output.parse_error`
+    /// unless `fail_on_error` is set
+    #[serde(default)]
+    pub parse: Option<ParseMode>,
+    /// How much output is kept in `output.stdout` / `output.stderr`
+    #[serde(default)]
+    pub capture: Capture,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ShellProgram {
+    Program(String),
+    Argv(Vec<String>),
+}
+
+impl ShellProgram {
+    fn argv(&self) -> Vec<String> {
+        match self {
+            ShellProgram::Program(program) => vec![program.clone(), "-c".to_string()],
+            ShellProgram::Argv(argv) => argv.clone(),
+        }
+    }
+}
+
+/// How stdout is structured
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseMode {
+    /// The whole of stdout as JSON
+    Json,
+    /// An array of the non-empty stdout lines
+    Lines,
+}
+
+/// Structure stdout per `mode`; truncated output is refused since it would parse to the wrong thing
+fn parse_stdout(mode: ParseMode, stdout: &Captured, max_bytes: usize) -> Result<Value> {
+    if stdout.truncated > 0 {
+        anyhow::bail!(
+            "Shell command output exceeded {} bytes and was truncated; raise 'capture.max_bytes' to parse it",
+            max_bytes
+        );
+    }
+    match mode {
+        ParseMode::Json => serde_json::from_str(stdout.text.trim())
+            .context("Shell command output is not valid JSON"),
+        ParseMode::Lines => Ok(stdout
+            .text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Value::String(line.to_string()))
+            .collect()),
+    }
+}
+
+fn default_exit_codes() -> Vec<i32> {
+    vec![0]
+}
+
 #[async_trait]
 impl NodeExecutor for ShellExecutor {
     async fn execute(
@@ -17,40 +107,144 @@ impl NodeExecutor for ShellExecutor {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
+        let params: ShellParams = parse_params(node)?;
         let template = TemplateEngine::new(global.clone(), nodes.clone());
-        
-        let command = node.params
-            .get("command")
-            .and_then(|v| v.as_str())
-            .context("Shell node requires 'command' parameter")?;
-
-        let rendered_command = template.render(command)?;
-        
+
+        let rendered_command = template.render(&params.command)?;
+        let argv = params
+            .shell
+            .as_ref()
+            .map(ShellProgram::argv)
+            .unwrap_or_else(|| vec!["sh".to_string(), "-c".to_string()]);
+        let stdin = match &params.stdin {
+            None => None,
+            Some(Value::String(s)) => Some(template.render(s)?.into_bytes()),
+            Some(value) => Some(serde_json::to_vec(&template.render_value(value)?)?),
+        };
+
         log::info!("Executing shell command: {}", rendered_command);
 
-        let output = Command::new("sh")
-            .arg("-c")
+        let mut command = Command::new(&argv[0]);
+        command
+            .args(&argv[1..])
             .arg(&rendered_command)
+            .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .await
-            .context("Failed to execute shell command")?;
-
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        let success = output.status.success();
-
-        let result = serde_json::json!({
-            "stdout": stdout.trim(),
-            "stderr": stderr.trim(),
-            "exit_code": output.status.code().unwrap_or(-1),
-            "success": success
-        });
+            .stderr(Stdio::piped());
+        for (key, value) in &params.env {
+            command.env(key, template.render(value)?);
+        }
+        if let Some(cwd) = &params.cwd {
+            command.current_dir(template.render(cwd)?);
+        }
+
+        let child = command
+            .spawn()
+            .with_context(|| format!("Failed to execute shell command with {}", argv[0]))?;
+        let output = process::run(child, stdin, global, &params.capture, &template).await?;
+
+        let exit_code = output.exit_code();
+        let success = params.allowed_exit_codes.contains(&exit_code);
+
+        if !success && params.fail_on_error {
+            anyhow::bail!("Shell command exited with code {}: {}", exit_code, output.stderr.text.trim());
+        }
+
+        // Parse whatever stdout there is, whether or not the command succeeded
+        let mut result = output.to_output(success);
+        if let Some(mode) = params.parse {
+            match parse_stdout(mode, &output.stdout, params.capture.max_bytes) {
+                Ok(parsed) => result["result"] = parsed,
+                Err(e) if params.fail_on_error => return Err(e),
+                Err(e) => result["parse_error"] = Value::String(format!("{:#}", e)),
+            }
+        }
 
         Ok(NodeOutput {
             status: if success { "success".to_string() } else { "failed".to_string() },
             output: result,
         })
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<ShellParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        let params: ShellParams = parse_params(node)?;
+        if let Some(ShellProgram::Argv(argv)) = &params.shell {
+            if argv.is_empty() {
+                anyhow::bail!("Shell node '{}': 'shell' must not be empty", node.id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn run(params: Value) -> Result<NodeOutput> {
+        let node: Node = serde_json::from_value(json!({"id": "sh", "type": "shell", "params": params})).unwrap();
+        let global = GlobalMemory::new();
+        global.set("name".to_string(), json!("ada")).unwrap();
+        ShellExecutor.execute(&node, &global, &NodeMemory::new()).await
+    }
+
+    #[tokio::test]
+    async fn env_cwd_and_stdin_are_templated() {
+        let dir = std::env::temp_dir();
+        let output = run(json!({
+            "command": "echo \"$GREETING\"; pwd; cat",
+            "env": {"GREETING": "hi {{ global.name }}"},
+            "cwd": dir.to_string_lossy(),
+            "stdin": {"who": "{{ global.name }}"},
+            "parse": "lines",
+        }))
+        .await
+        .unwrap();
+        let canonical = dir.canonicalize().unwrap();
+        assert_eq!(
+            output.output["result"],
+            json!(["hi ada", canonical.to_string_lossy(), r#"{"who":"ada"}"#])
+        );
+    }
+
+    #[tokio::test]
+    async fn exit_codes_decide_the_status() {
+        let allowed = run(json!({"command": "exit 3", "allowed_exit_codes": [0, 3]})).await.unwrap();
+        assert_eq!(allowed.status, "success");
+
+        let failed = run(json!({"command": "echo partial; exit 3", "parse": "lines"})).await.unwrap();
+        assert_eq!(failed.status, "failed");
+        assert_eq!(failed.output["exit_code"], json!(3));
+        assert_eq!(failed.output["result"], json!(["partial"]));
+
+        let err = run(json!({"command": "echo oops >&2; exit 3", "fail_on_error": true})).await.unwrap_err();
+        assert_eq!(err.to_string(), "Shell command exited with code 3: oops");
+    }
+
+    #[tokio::test]
+    async fn parse_failures_are_reported_in_the_output() {
+        let output = run(json!({"command": "echo not json", "parse": "json"})).await.unwrap();
+        assert_eq!(output.status, "success");
+        assert!(output.output.get("result").is_none());
+        assert!(output.output["parse_error"].as_str().unwrap().starts_with("Shell command output is not valid JSON"));
+
+        let truncated = run(json!({"command": "echo '[1, 2, 3]'", "parse": "json", "capture": {"max_bytes": 4}})).await.unwrap();
+        assert!(truncated.output["parse_error"].as_str().unwrap().contains("truncated"));
+
+        assert!(run(json!({"command": "echo nope", "parse": "json", "fail_on_error": true})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn shell_may_be_an_argv_prefix() {
+        let output = run(json!({"command": "echo ${BASH_VERSION:+bash}", "shell": ["bash", "-c"]})).await.unwrap();
+        assert_eq!(output.output["stdout"], json!("bash"));
+
+        let node: Node = serde_json::from_value(json!({"id": "sh", "type": "shell", "params": {"command": "true", "shell": []}})).unwrap();
+        assert!(ShellExecutor.validate(&node).is_err());
+    }
 }
@@ -1,13 +1,29 @@
-use crate::nodes::NodeExecutor;
+use crate::nodes::{params_schema, parse_params, NodeExecutor};
 use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
 use crate::schema::Node;
 use crate::template::TemplateEngine;
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
 use serde_json::Value;
+use std::collections::BTreeMap;
 
 pub struct TransformExecutor;
 
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TransformParams {
+    /// Data to query; strings are rendered and parsed as JSON when possible
+    pub input: Value,
+    /// Single JSONPath extraction, output as `result`
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Output field -> JSONPath
+    #[serde(default)]
+    pub extract: Option<BTreeMap<String, String>>,
+}
+
 #[async_trait]
 impl NodeExecutor for TransformExecutor {
     async fn execute(
@@ -16,11 +32,9 @@ impl NodeExecutor for TransformExecutor {
         global: &GlobalMemory,
         nodes: &NodeMemory,
     ) -> Result<NodeOutput> {
+        let params: TransformParams = parse_params(node)?;
         let template = TemplateEngine::new(global.clone(), nodes.clone());
-        
-        let input = node.params
-            .get("input")
-            .context("Transform node requires 'input' parameter")?;
+        let input = &params.input;
 
         // Render input if it's a string
         let input_value: Value = if let Some(input_str) = input.as_str() {
@@ -34,7 +48,7 @@ impl NodeExecutor for TransformExecutor {
         log::info!("Transforming data with JSONPath");
 
         // Single path extraction
-        if let Some(path) = node.params.get("path").and_then(|v| v.as_str()) {
+        if let Some(path) = &params.path {
             let mut selector = jsonpath_lib::selector(&input_value);
             let result_vec = selector(path)
                 .context(format!("JSONPath '{}' evaluation failed", path))?;
@@ -49,19 +63,17 @@ impl NodeExecutor for TransformExecutor {
         }
 
         // Multiple field extraction
-        if let Some(extract_obj) = node.params.get("extract").and_then(|v| v.as_object()) {
+        if let Some(extract_obj) = &params.extract {
             let mut result = serde_json::Map::new();
             
-            for (key, path_value) in extract_obj {
-                if let Some(path) = path_value.as_str() {
-                    let mut selector = jsonpath_lib::selector(&input_value);
-                    let extracted_vec = selector(path)
-                        .context(format!("JSONPath '{}' evaluation failed", path))?;
-                    
-                    // Convert Vec<&Value> to Value
-                    let extracted = Value::Array(extracted_vec.into_iter().cloned().collect());
-                    result.insert(key.clone(), extracted);
-                }
+            for (key, path) in extract_obj {
+                let mut selector = jsonpath_lib::selector(&input_value);
+                let extracted_vec = selector(path)
+                    .context(format!("JSONPath '{}' evaluation failed", path))?;
+                
+                // Convert Vec<&Value> to Value
+                let extracted = Value::Array(extracted_vec.into_iter().cloned().collect());
+                result.insert(key.clone(), extracted);
             }
             
             return Ok(NodeOutput {
@@ -72,4 +84,12 @@ impl NodeExecutor for TransformExecutor {
 
         anyhow::bail!("Transform node requires either 'path' or 'extract' parameter")
     }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<TransformParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        parse_params::<TransformParams>(node).map(drop)
+    }
 }
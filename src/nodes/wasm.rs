@@ -0,0 +1,300 @@
+//! Sandboxed execution of WASI modules for `language: wasm` script nodes.
+//!
+//! The guest sees only what the node grants: its args and env, the JSON
+//! `inputs` on stdin, the script (if any) at `/script/main`, and the listed
+//! directories. There is no network access. Memory is capped per instance and
+//! a run is interrupted once its time limit passes.
+
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+use wasmtime_wasi::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::{DirPerms, FilePerms, I32Exit, WasiCtxBuilder};
+
+/// Granularity of the time limit: the shared engine's epoch advances this often
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Cap on captured stdout/stderr per stream
+const OUTPUT_CAPACITY: usize = 16 * 1024 * 1024;
+
+/// Guest directory holding the node's `script`
+const SCRIPT_DIR: &str = "/script";
+
+/// Limits and grants for a wasm script
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Sandbox {
+    /// Maximum linear memory per instance, in MiB
+    #[serde(default = "default_memory_mb")]
+    pub memory_mb: usize,
+    /// Wall-clock limit, in milliseconds
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Host directories made visible to the guest; none by default
+    #[serde(default)]
+    pub dirs: Vec<DirGrant>,
+    /// Environment variables for the guest; the host environment is never inherited
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            memory_mb: default_memory_mb(),
+            timeout_ms: default_timeout_ms(),
+            dirs: Vec::new(),
+            env: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_memory_mb() -> usize {
+    64
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DirGrant {
+    /// Directory on the host
+    pub host: String,
+    /// Path the guest opens it under
+    pub guest: String,
+    /// Allow creating and modifying files; read-only by default
+    #[serde(default)]
+    pub writable: bool,
+}
+
+/// What a wasm module is run with
+pub struct WasmRun {
+    /// `.wasm` or `.wat` file
+    pub module: PathBuf,
+    pub args: Vec<String>,
+    pub script: Option<String>,
+    pub stdin: Value,
+    pub sandbox: Sandbox,
+}
+
+pub struct WasmOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+    /// Set when the module was stopped by a limit rather than exiting
+    pub error: Option<String>,
+}
+
+struct GuestState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+/// One engine for all runs so compiled modules can be cached; a background
+/// thread advances its epoch, which is what enforces time limits
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).expect("wasm engine configuration is valid");
+
+        let ticker = engine.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            ticker.increment_epoch();
+        });
+        engine
+    })
+}
+
+/// Compile a module, reusing the compiled form until the file changes
+fn load_module(path: &Path) -> Result<Module> {
+    static MODULES: OnceLock<DashMap<PathBuf, (Option<SystemTime>, Module)>> = OnceLock::new();
+    let modules = MODULES.get_or_init(DashMap::new);
+
+    let modified = std::fs::metadata(path)
+        .with_context(|| format!("Wasm module `{:?}` not found", path))?
+        .modified()
+        .ok();
+    if let Some(entry) = modules.get(path) {
+        if entry.0 == modified {
+            return Ok(entry.1.clone());
+        }
+    }
+
+    let module = Module::from_file(engine(), path)
+        .with_context(|| format!("Failed to compile wasm module `{:?}`", path))?;
+    modules.insert(path.to_path_buf(), (modified, module.clone()));
+    Ok(module)
+}
+
+/// Run a WASI command module to completion inside its sandbox
+pub async fn run(run: WasmRun) -> Result<WasmOutput> {
+    tokio::task::spawn_blocking(move || run_blocking(run))
+        .await
+        .context("Wasm task failed")?
+}
+
+fn run_blocking(run: WasmRun) -> Result<WasmOutput> {
+    let module = load_module(&run.module)?;
+    let sandbox = &run.sandbox;
+
+    let stdout = MemoryOutputPipe::new(OUTPUT_CAPACITY);
+    let stderr = MemoryOutputPipe::new(OUTPUT_CAPACITY);
+
+    let mut wasi = WasiCtxBuilder::new();
+    wasi.stdin(MemoryInputPipe::new(serde_json::to_vec(&run.stdin)?))
+        .stdout(stdout.clone())
+        .stderr(stderr.clone());
+
+    // argv[0] is the module name, as for a native command
+    let program = run
+        .module
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    wasi.arg(program);
+    wasi.args(&run.args);
+    for (key, value) in &sandbox.env {
+        wasi.env(key, value);
+    }
+
+    // The script is shared read-only through a directory of its own
+    let script_dir = match &run.script {
+        Some(script) => {
+            let dir = std::env::temp_dir().join(format!("workflow_wasm_{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir)?;
+            std::fs::write(dir.join("main"), script).context("Failed to write wasm script")?;
+            wasi.preopened_dir(&dir, SCRIPT_DIR, DirPerms::READ, FilePerms::READ)?;
+            Some(dir)
+        }
+        None => None,
+    };
+    for grant in &sandbox.dirs {
+        let (dir_perms, file_perms) = if grant.writable {
+            (DirPerms::all(), FilePerms::all())
+        } else {
+            (DirPerms::READ, FilePerms::READ)
+        };
+        wasi.preopened_dir(&grant.host, &grant.guest, dir_perms, file_perms)
+            .with_context(|| format!("Cannot grant directory `{}` to wasm module", grant.host))?;
+    }
+
+    let limits = StoreLimitsBuilder::new()
+        .memory_size(sandbox.memory_mb * 1024 * 1024)
+        .instances(1)
+        .build();
+    let mut store = Store::new(engine(), GuestState { wasi: wasi.build_p1(), limits });
+    store.limiter(|state| &mut state.limits);
+    store.set_epoch_deadline((sandbox.timeout_ms / EPOCH_TICK.as_millis() as u64).max(1));
+
+    let mut linker: Linker<GuestState> = Linker::new(engine());
+    preview1::add_to_linker_sync(&mut linker, |state| &mut state.wasi)?;
+
+    let result = linker
+        .instantiate(&mut store, &module)
+        .and_then(|instance| instance.get_typed_func::<(), ()>(&mut store, "_start"))
+        .and_then(|start| start.call(&mut store, ()));
+    drop(store);
+    if let Some(dir) = script_dir {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    let (exit_code, error) = match result {
+        Ok(()) => (0, None),
+        Err(e) => match e.downcast_ref::<I32Exit>() {
+            Some(exit) => (exit.0, None),
+            None if e.downcast_ref::<Trap>() == Some(&Trap::Interrupt) => {
+                (-1, Some(format!("Wasm module exceeded its time limit of {}ms", sandbox.timeout_ms)))
+            }
+            None => (-1, Some(format!("Wasm module trapped: {:#}", e))),
+        },
+    };
+
+    Ok(WasmOutput {
+        stdout: String::from_utf8_lossy(&stdout.contents()).to_string(),
+        stderr: String::from_utf8_lossy(&stderr.contents()).to_string(),
+        exit_code,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Write a `.wat` module to a temp file
+    fn module(wat: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("workflow-engine-wasm-{}.wat", uuid::Uuid::new_v4()));
+        std::fs::write(&path, wat).unwrap();
+        path
+    }
+
+    fn sandbox(memory_mb: usize, timeout_ms: u64) -> Sandbox {
+        Sandbox { memory_mb, timeout_ms, ..Sandbox::default() }
+    }
+
+    #[tokio::test]
+    async fn guest_reads_inputs_from_stdin() {
+        let module = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples/wasm/echo.wat");
+        let output = run(WasmRun {
+            module,
+            args: Vec::new(),
+            script: None,
+            stdin: json!({"tags": ["a"]}),
+            sandbox: Sandbox::default(),
+        })
+        .await
+        .unwrap();
+        assert_eq!(output.exit_code, 0);
+        assert!(output.error.is_none());
+        assert_eq!(serde_json::from_str::<Value>(&output.stdout).unwrap(), json!({"echo": {"tags": ["a"]}}));
+    }
+
+    #[tokio::test]
+    async fn endless_loop_hits_the_time_limit() {
+        let path = module(r#"(module (func (export "_start") (loop $l (br $l))))"#);
+        let output = run(WasmRun {
+            module: path.clone(),
+            args: Vec::new(),
+            script: None,
+            stdin: Value::Null,
+            sandbox: sandbox(16, 100),
+        })
+        .await
+        .unwrap();
+        assert_eq!(output.exit_code, -1);
+        assert_eq!(output.error.as_deref(), Some("Wasm module exceeded its time limit of 100ms"));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn memory_above_the_limit_is_refused() {
+        // 32 pages of 64 KiB is 2 MiB
+        let path = module(r#"(module (memory (export "memory") 32) (func (export "_start")))"#);
+        let run_with = |memory_mb| {
+            run(WasmRun {
+                module: path.clone(),
+                args: Vec::new(),
+                script: None,
+                stdin: Value::Null,
+                sandbox: sandbox(memory_mb, 1_000),
+            })
+        };
+        assert!(run_with(1).await.unwrap().error.is_some());
+        assert!(run_with(4).await.unwrap().error.is_none());
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,205 @@
+use crate::nodes::{params_schema, parse_params, ExecutorRegistry, NodeExecutor, WeakRegistry};
+use crate::memory::{NodeOutput, GlobalMemory, NodeMemory};
+use crate::schema::Node;
+use crate::template::TemplateEngine;
+use crate::engine::Engine;
+use crate::{inputs, loader};
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Default limit for nested `workflow` nodes
+const DEFAULT_MAX_DEPTH: usize = 8;
+
+pub struct WorkflowExecutor {
+    /// Executors for the sub-workflow; weak because the registry owns this executor
+    executors: WeakRegistry,
+}
+
+impl WorkflowExecutor {
+    pub fn new(executors: &ExecutorRegistry) -> Self {
+        Self { executors: executors.downgrade() }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WorkflowParams {
+    /// Sub-workflow file, relative to the calling workflow (templated)
+    pub path: String,
+    /// Values for the sub-workflow's declared inputs; strings are templated
+    #[serde(default)]
+    pub inputs: serde_json::Map<String, Value>,
+    /// Maximum nesting of sub-workflows
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+}
+
+/// `a.yaml -> b.yaml -> ...` for error messages
+fn chain(files: &[PathBuf]) -> String {
+    files.iter().map(|f| f.display().to_string()).collect::<Vec<_>>().join(" -> ")
+}
+
+#[async_trait]
+impl NodeExecutor for WorkflowExecutor {
+    async fn execute(
+        &self,
+        node: &Node,
+        global: &GlobalMemory,
+        nodes: &NodeMemory,
+    ) -> Result<NodeOutput> {
+        let params: WorkflowParams = parse_params(node)?;
+        let template = TemplateEngine::new(global.clone(), nodes.clone());
+
+        let path = template.render(&params.path)?;
+        let max_depth = params.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+
+        let file = Path::new(&path)
+            .canonicalize()
+            .with_context(|| format!("Sub-workflow '{}' not found", path))?;
+
+        // Callers above this node, outermost first, then this call
+        let mut call_stack = global.call_stack().to_vec();
+        let cycle = call_stack.iter().position(|caller| *caller == file);
+        call_stack.push(file.clone());
+        if let Some(start) = cycle {
+            anyhow::bail!("Sub-workflow cycle detected: {}", chain(&call_stack[start..]));
+        }
+        if call_stack.len() > max_depth {
+            anyhow::bail!("Sub-workflow depth limit ({}) exceeded: {}", max_depth, chain(&call_stack));
+        }
+
+        let mut workflow = tokio::task::spawn_blocking(move || loader::load_workflow_cached(&file))
+            .await?
+            .with_context(|| format!("Failed to load sub-workflow '{}'", path))?;
+
+        // Map caller values into the sub-workflow's declared inputs
+        let provided: HashMap<String, Value> = params.inputs
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), template.render_value(v)?)))
+            .collect::<Result<_>>()?;
+        inputs::apply_inputs(&mut workflow, provided)
+            .and_then(|_| inputs::check_files(&workflow))
+            .with_context(|| format!("Invalid inputs for sub-workflow '{}'", path))?;
+
+        log::info!("Running sub-workflow '{}' from {} (depth {})", workflow.name, path, call_stack.len());
+
+        // Fresh globals in the caller's backend; the session, events and journal are
+        // shared, and the call stack lets nested workflow nodes see their callers
+        let (sub_global, sub_nodes) = global.sub_run()?;
+        let engine = Engine::with_memory(workflow, sub_global.with_call_stack(call_stack), sub_nodes)?
+            .with_executors(self.executors.upgrade()?);
+        engine.execute().await
+            .with_context(|| format!("Sub-workflow '{}' failed", path))?;
+
+        // Expose declared outputs, or every node output when none are declared
+        let output = match engine.resolve_outputs()? {
+            Some(outputs) => Value::Object(outputs),
+            None => serde_json::to_value(engine.get_node_memory().get_all_values()?)?,
+        };
+
+        Ok(NodeOutput {
+            status: "success".to_string(),
+            output,
+        })
+    }
+
+    fn params_schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        params_schema::<WorkflowParams>(generator)
+    }
+
+    fn validate(&self, node: &Node) -> Result<()> {
+        parse_params::<WorkflowParams>(node).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::RunEvent;
+    use crate::storage::MemoryBackend;
+    use crate::{loader, Engine};
+    use std::path::PathBuf;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workflow-engine-sub-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn calling(target: &str, extra: &str) -> String {
+        format!(
+            "name: caller\nversion: '2.0'\nnodes:\n  - id: call\n    type: workflow\n    params: {{path: {}{}}}\n",
+            target, extra
+        )
+    }
+
+    const INNER: &str = "name: inner\nversion: '2.0'\nnodes:\n  - id: step\n    type: assign\n    params: {assignments: [{key: x, value: 1}]}\n";
+
+    #[tokio::test]
+    async fn cycle_is_reported_at_the_first_repeat() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("a.yaml"), calling("b.yaml", "")).unwrap();
+        std::fs::write(dir.join("b.yaml"), calling("a.yaml", "")).unwrap();
+
+        let engine = Engine::builder().workflow(loader::load_workflow(&dir.join("a.yaml")).unwrap()).build().unwrap();
+        let err = format!("{:#}", engine.run().await.unwrap_err());
+        let a = dir.canonicalize().unwrap().join("a.yaml");
+        let b = dir.canonicalize().unwrap().join("b.yaml");
+        let expected = format!("Sub-workflow cycle detected: {} -> {} -> {}", a.display(), b.display(), a.display());
+        assert!(err.contains(&expected), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn max_depth_counts_the_calling_file() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("main.yaml"), calling("inner.yaml", ", max_depth: 1")).unwrap();
+        std::fs::write(dir.join("inner.yaml"), INNER).unwrap();
+
+        let engine = Engine::builder().workflow(loader::load_workflow(&dir.join("main.yaml")).unwrap()).build().unwrap();
+        let err = format!("{:#}", engine.run().await.unwrap_err());
+        assert!(err.contains("Sub-workflow depth limit (1) exceeded"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sub_run_shares_events_and_backend() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("main.yaml"), calling("inner.yaml", "")).unwrap();
+        std::fs::write(dir.join("inner.yaml"), INNER).unwrap();
+        let store = dir.join("memory");
+
+        let engine = Engine::builder()
+            .workflow(loader::load_workflow(&dir.join("main.yaml")).unwrap())
+            .memory(MemoryBackend::Json(store.clone()))
+            .run_id("run1")
+            .build()
+            .unwrap();
+        let mut events = engine.subscribe();
+        let result = engine.run().await.unwrap();
+
+        let mut completed = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let RunEvent::NodeCompleted { node, .. } = event {
+                completed.push(node);
+            }
+        }
+        assert_eq!(completed, ["call/step", "call"]);
+
+        let journal: Vec<_> = result.journal.iter().map(|e| (e.node.as_deref(), e.key.as_str())).collect();
+        assert_eq!(journal, [(Some("call/step"), "x")]);
+
+        let sub_globals: Vec<String> = std::fs::read_dir(&store)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with("run1%2Fcall%2F") && name.ends_with("%2Fglobal"))
+            .collect();
+        assert_eq!(sub_globals.len(), 1);
+        assert!(store.join(&sub_globals[0]).join("x.json").is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! Plan-time rewriting of the node list before scheduling.
+//!
+//! A node with `matrix:` is expanded into one node per combination
+//! (`<id>_0`, `<id>_1`, ...) plus a `join` node that keeps the original id,
+//! so downstream `needs` and `{{ nodes.<id>.output }}` see the collected results.
+
+use crate::memory::{GlobalMemory, NodeMemory};
+use crate::schema::Node;
+use crate::template::TemplateEngine;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Expand every `matrix:` node; axes given as templates are evaluated against `global`
+pub fn expand_matrix(nodes: &[Node], global: &GlobalMemory) -> Result<Vec<Node>> {
+    if nodes.iter().all(|n| n.matrix.is_none()) {
+        check_unique(nodes)?;
+        return Ok(nodes.to_vec());
+    }
+
+    let template = TemplateEngine::new(global.clone(), NodeMemory::new());
+    let mut planned = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        let Some(matrix) = &node.matrix else {
+            planned.push(node.clone());
+            continue;
+        };
+
+        let combos = combinations(matrix, &template)
+            .with_context(|| format!("Invalid matrix on node '{}'", node.id))?;
+        if combos.is_empty() {
+            anyhow::bail!("Matrix on node '{}' produces no combinations", node.id);
+        }
+
+        let mut ids = Vec::with_capacity(combos.len());
+        for (index, combo) in combos.iter().enumerate() {
+            let id = format!("{}_{}", node.id, index);
+            let label = combo
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, display(v)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let base_name = if node.name.is_empty() { &node.id } else { &node.name };
+
+            let mut params = node.params.clone();
+            substitute(&mut params, combo)
+                .with_context(|| format!("Failed to expand matrix on node '{}'", node.id))?;
+
+            planned.push(Node {
+                id: id.clone(),
+                name: format!("{} [{}]", base_name, label),
+                node_type: node.node_type.clone(),
+                needs: node.needs.clone(),
+                params,
+                matrix: None,
+            });
+            ids.push(id);
+        }
+
+        log::info!("Expanded matrix node '{}' into {} nodes", node.id, ids.len());
+
+        planned.push(Node {
+            id: node.id.clone(),
+            name: node.name.clone(),
+            node_type: "join".to_string(),
+            needs: Some(ids.clone()),
+            params: serde_json::json!({
+                "nodes": ids,
+                "matrix": combos.into_iter().map(Value::Object).collect::<Vec<_>>(),
+            }),
+            matrix: None,
+        });
+    }
+
+    check_unique(&planned)?;
+    Ok(planned)
+}
+
+/// Two nodes with one id would silently replace each other in the scheduler
+fn check_unique(nodes: &[Node]) -> Result<()> {
+    let mut seen = HashSet::new();
+    let mut clashes: Vec<&str> = Vec::new();
+    for node in nodes {
+        if !seen.insert(node.id.as_str()) && !clashes.contains(&node.id.as_str()) {
+            clashes.push(&node.id);
+        }
+    }
+    if !clashes.is_empty() {
+        anyhow::bail!(
+            "Duplicate node ids: {} (matrix nodes generate '<id>_0', '<id>_1', ...; rename the clashing node)",
+            clashes.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Cartesian product of the axes in declared order, then `exclude` filters and `include` extras
+fn combinations(matrix: &Value, template: &TemplateEngine) -> Result<Vec<Map<String, Value>>> {
+    let Value::Object(spec) = matrix else {
+        anyhow::bail!("'matrix' must be a mapping of axis name to list of values");
+    };
+
+    let mut combos = vec![Map::new()];
+    for (axis, values) in spec {
+        if axis == "include" || axis == "exclude" {
+            continue;
+        }
+        let values = match values {
+            Value::String(expr) => template.evaluate(expr)?,
+            other => other.clone(),
+        };
+        let Value::Array(values) = values else {
+            anyhow::bail!("Matrix axis '{}' must be a list", axis);
+        };
+
+        combos = combos
+            .into_iter()
+            .flat_map(|combo| {
+                values.iter().map(move |value| {
+                    let mut combo = combo.clone();
+                    combo.insert(axis.clone(), value.clone());
+                    combo
+                })
+            })
+            .collect();
+    }
+
+    if let Some(exclude) = spec.get("exclude") {
+        let patterns = as_combos(exclude, "exclude")?;
+        combos.retain(|combo| !patterns.iter().any(|p| p.iter().all(|(k, v)| combo.get(k) == Some(v))));
+    }
+    if let Some(include) = spec.get("include") {
+        combos.extend(as_combos(include, "include")?);
+    }
+
+    // A matrix made only of `include` starts from a single empty combination
+    combos.retain(|combo| !combo.is_empty());
+    Ok(combos)
+}
+
+fn as_combos(value: &Value, field: &str) -> Result<Vec<Map<String, Value>>> {
+    serde_json::from_value(value.clone())
+        .with_context(|| format!("Matrix '{}' must be a list of mappings", field))
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn matrix_ref() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\{\{\s*matrix\.([A-Za-z0-9_.]+)\s*\}\}").unwrap())
+}
+
+/// Replace `{{ matrix.<axis> }}` references; a string that is exactly one
+/// reference takes the value as-is so numbers and lists keep their type
+fn substitute(value: &mut Value, combo: &Map<String, Value>) -> Result<()> {
+    match value {
+        Value::String(s) => {
+            let re = matrix_ref();
+            if !re.is_match(s) {
+                return Ok(());
+            }
+            if let Some(caps) = re.captures(s).filter(|c| c[0].len() == s.len()) {
+                *value = lookup(combo, &caps[1])?;
+                return Ok(());
+            }
+
+            let mut error = None;
+            let rendered = re.replace_all(s, |caps: &regex::Captures| match lookup(combo, &caps[1]) {
+                Ok(v) => display(&v),
+                Err(e) => {
+                    error.get_or_insert(e);
+                    String::new()
+                }
+            });
+            if let Some(e) = error {
+                return Err(e);
+            }
+            *s = rendered.into_owned();
+        }
+        Value::Array(items) => {
+            for item in items {
+                substitute(item, combo)?;
+            }
+        }
+        Value::Object(map) => {
+            for (_, item) in map.iter_mut() {
+                substitute(item, combo)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn lookup(combo: &Map<String, Value>, path: &str) -> Result<Value> {
+    let mut parts = path.split('.');
+    let axis = parts.next().unwrap_or_default();
+    let mut current = combo
+        .get(axis)
+        .with_context(|| format!("Unknown matrix variable 'matrix.{}'", axis))?;
+    for part in parts {
+        current = match current {
+            Value::Object(map) => map.get(part),
+            Value::Array(items) => part.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        }
+        .with_context(|| format!("Matrix variable 'matrix.{}' not found", path))?;
+    }
+    Ok(current.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(value: Value) -> Node {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn expands_axes_in_declared_order_with_a_join() {
+        let nodes = vec![node(json!({
+            "id": "build",
+            "type": "shell",
+            "matrix": {"os": ["linux", "mac"], "v": [1, 2]},
+            "params": {"command": "build {{ matrix.os }}", "version": "{{ matrix.v }}"}
+        }))];
+        let planned = expand_matrix(&nodes, &GlobalMemory::new()).unwrap();
+
+        let ids: Vec<&str> = planned.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["build_0", "build_1", "build_2", "build_3", "build"]);
+        assert_eq!(planned[1].name, "build [os=linux, v=2]");
+        assert_eq!(planned[2].params, json!({"command": "build mac", "version": 1}));
+
+        let join = &planned[4];
+        assert_eq!(join.node_type, "join");
+        assert_eq!(join.needs.as_deref().unwrap(), ["build_0", "build_1", "build_2", "build_3"]);
+        assert_eq!(join.params["matrix"][3], json!({"os": "mac", "v": 2}));
+    }
+
+    #[test]
+    fn exclude_include_and_template_axes() {
+        let global = GlobalMemory::new();
+        global.set("regions".to_string(), json!(["eu", "us"])).unwrap();
+        let nodes = vec![node(json!({
+            "id": "deploy",
+            "type": "shell",
+            "matrix": {
+                "region": "{{ global.regions }}",
+                "tier": ["a", "b"],
+                "exclude": [{"region": "us", "tier": "b"}],
+                "include": [{"region": "ap", "tier": "a"}]
+            },
+            "params": {"command": "{{ matrix.region }}-{{ matrix.tier }}"}
+        }))];
+        let planned = expand_matrix(&nodes, &global).unwrap();
+
+        let commands: Vec<&Value> = planned.iter().filter(|n| n.node_type == "shell").map(|n| &n.params["command"]).collect();
+        assert_eq!(commands, [&json!("eu-a"), &json!("eu-b"), &json!("us-a"), &json!("ap-a")]);
+    }
+
+    #[test]
+    fn rejects_clashing_ids_and_unknown_variables() {
+        let clash = vec![
+            node(json!({"id": "t", "type": "shell", "matrix": {"x": [1]}, "params": {}})),
+            node(json!({"id": "t_0", "type": "shell", "params": {}})),
+        ];
+        let err = expand_matrix(&clash, &GlobalMemory::new()).unwrap_err();
+        assert!(err.to_string().contains("Duplicate node ids: t_0"), "{}", err);
+
+        let unknown = vec![node(json!({
+            "id": "t", "type": "shell", "matrix": {"x": [1]}, "params": {"command": "{{ matrix.y }}"}
+        }))];
+        let err = expand_matrix(&unknown, &GlobalMemory::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains("matrix.y"), "{:#}", err);
+
+        let empty = vec![node(json!({"id": "t", "type": "shell", "matrix": {"x": []}, "params": {}}))];
+        assert!(expand_matrix(&empty, &GlobalMemory::new()).is_err());
+    }
+}
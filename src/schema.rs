@@ -1,16 +1,37 @@
+use crate::http_pool::HttpConfig;
+use crate::nodes;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::collections::HashMap;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, JsonSchema)]
 pub struct Workflow {
     pub name: String,
+    /// DSL schema version (see `migrate::CURRENT_VERSION`); older documents are migrated on load
     pub version: String,
+    /// Declared inputs, validated before the run and written into global memory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<InputSpec>,
     #[serde(default)]
     pub global: HashMap<String, serde_json::Value>,
+    /// Conversation variables with their initial values; kept per session across runs
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub conversation: HashMap<String, serde_json::Value>,
     pub nodes: Vec<Node>,
+    /// Result of the run: output name -> template expression (e.g. `{{ nodes.report.output.content }}`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub outputs: HashMap<String, serde_json::Value>,
+    /// Per-host concurrency caps and rate limits for outgoing HTTP
+    #[serde(default, skip_serializing_if = "HttpConfig::is_empty")]
+    pub http: HttpConfig,
+    /// File the workflow was loaded from, if any
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub source: Option<std::path::PathBuf>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct Node {
     pub id: String,
     #[serde(default)]
@@ -21,4 +42,107 @@ pub struct Node {
     pub needs: Option<Vec<String>>,
     #[serde(default)]
     pub params: serde_json::Value,
+    /// Axis name -> values; the node is expanded into one node per combination at plan time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct InputSpec {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub input_type: InputType,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    /// Allowed values
+    #[serde(default, rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum InputType {
+    #[default]
+    String,
+    Number,
+    #[serde(alias = "boolean")]
+    Bool,
+    Array,
+    Object,
+    /// Path to an existing file, passed on as a string
+    File,
+}
+
+impl std::fmt::Display for InputType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            InputType::String => "string",
+            InputType::Number => "number",
+            InputType::Bool => "bool",
+            InputType::Array => "array",
+            InputType::Object => "object",
+            InputType::File => "file",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// JSON Schema of the workflow DSL, with `params` checked per registered node `type`
+pub fn json_schema(executors: &nodes::ExecutorRegistry) -> serde_json::Value {
+    let mut generator = schemars::SchemaGenerator::default();
+
+    // `if type == X then params: XParams` for every node type
+    let mut by_type = Vec::new();
+    let node_types = executors.types();
+    for node_type in &node_types {
+        let Ok(executor) = executors.get(node_type) else { continue };
+        let params = executor.params_schema(&mut generator);
+        by_type.push(json!({
+            "if": { "properties": { "type": { "const": node_type } }, "required": ["type"] },
+            "then": { "properties": { "params": params } },
+        }));
+    }
+
+    let runtime_spec = generator.subschema_for::<nodes::runtime::RuntimeSpec>().to_value();
+    let mut root = generator.into_root_schema_for::<Workflow>().to_value();
+
+    // Composition keys resolved by the loader before deserialization
+    root["properties"]["include"] = json!({
+        "description": "Fragment file(s) merged into this workflow, relative to this file",
+        "anyOf": [{ "type": "string" }, { "type": "array", "items": { "type": "string" } }],
+    });
+    root["properties"]["templates"] = json!({
+        "description": "Reusable node definitions that nodes can `extends:`",
+        "type": "object",
+        "additionalProperties": { "type": "object" },
+    });
+
+    root["properties"]["runtimes"] = json!({
+        "description": "Script runtime settings by language, applied to the script nodes using them",
+        "type": "object",
+        "additionalProperties": runtime_spec,
+    });
+
+    let node = &mut root["$defs"]["Node"];
+    node["properties"]["type"]["enum"] = json!(node_types);
+    node["properties"]["extends"] = json!({
+        "description": "Template this node is based on",
+        "type": "string",
+    });
+    node["properties"]["with"] = json!({
+        "description": "Values for `${name}` placeholders in the template",
+        "type": "object",
+    });
+    // A node extending a template may take its `type` from the template
+    if let Some(required) = node["required"].as_array_mut() {
+        required.retain(|field| field != "type");
+    }
+    node["anyOf"] = json!([{ "required": ["type"] }, { "required": ["extends"] }]);
+    node["allOf"] = json!(by_type);
+
+    root
 }
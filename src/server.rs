@@ -1,5 +1,5 @@
 use axum::{
-    extract::Json,
+    extract::{Json, State},
     routing::post,
     Router,
 };
@@ -7,26 +7,55 @@ use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use std::collections::HashMap;
 use serde_json::Value;
-use anyhow::Context;
 use crate::engine::Engine;
-use crate::schema;
+use crate::memory::JournalEntry;
+use crate::loader;
+use crate::nodes::ExecutorRegistry;
+use crate::storage::MemoryBackend;
+
+#[derive(Clone)]
+struct ServerState {
+    backend: MemoryBackend,
+    executors: ExecutorRegistry,
+}
 
 #[derive(Deserialize)]
 pub struct ExecuteRequest {
     pub file: String,
     pub inputs: Option<HashMap<String, Value>>,
+    /// Reuse a run ID to inspect or continue persisted memory
+    pub run_id: Option<String>,
+    /// Session whose conversation variables persist across requests
+    pub session_id: Option<String>,
+    /// Also return full memory (globals, node outputs, journal)
+    #[serde(default)]
+    pub debug: bool,
 }
 
 #[derive(Serialize)]
 pub struct ExecuteResponse {
+    pub run_id: String,
     pub status: String,
+    /// Declared workflow outputs, or every node output when none are declared
     pub outputs: HashMap<String, Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug: Option<DebugMemory>,
     pub error: Option<String>,
 }
 
-pub async fn run_server(port: u16) -> anyhow::Result<()> {
+/// Full memory of a run, returned when the request sets `debug`
+#[derive(Serialize)]
+pub struct DebugMemory {
+    pub global_memory: HashMap<String, Value>,
+    pub node_outputs: HashMap<String, Value>,
+    /// Writes to global/conversation variables, in order
+    pub journal: Vec<JournalEntry>,
+}
+
+pub async fn run_server(port: u16, backend: MemoryBackend, executors: ExecutorRegistry) -> anyhow::Result<()> {
     let app = Router::new()
-        .route("/execute", post(handle_execute));
+        .route("/execute", post(handle_execute))
+        .with_state(ServerState { backend, executors });
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     log::info!("🚀 Server listening on http://{}", addr);
@@ -38,48 +67,66 @@ pub async fn run_server(port: u16) -> anyhow::Result<()> {
 }
 
 async fn handle_execute(
-    Json(payload): Json<ExecuteRequest>,
+    State(state): State<ServerState>,
+    Json(mut payload): Json<ExecuteRequest>,
 ) -> Json<ExecuteResponse> {
     log::info!("Received execution request for file: {}", payload.file);
 
-    match execute_workflow(payload).await {
-        Ok(outputs) => Json(ExecuteResponse {
+    let run_id = payload
+        .run_id
+        .take()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    match execute_workflow(payload, &state, &run_id).await {
+        Ok((outputs, debug)) => Json(ExecuteResponse {
+            run_id,
             status: "success".to_string(),
             outputs,
+            debug,
             error: None,
         }),
         Err(e) => {
             log::error!("Execution failed: {}", e);
             Json(ExecuteResponse {
+                run_id,
                 status: "error".to_string(),
                 outputs: HashMap::new(),
+                debug: None,
                 error: Some(e.to_string()),
             })
         }
     }
 }
 
-async fn execute_workflow(req: ExecuteRequest) -> anyhow::Result<HashMap<String, Value>> {
-    // Read workflow file
-    let content = tokio::fs::read_to_string(&req.file)
-        .await
-        .with_context(|| format!("Could not read file `{}`", req.file))?;
-
-    // Parse workflow
-    let mut workflow: schema::Workflow = serde_yaml::from_str(&content)
-        .context("Failed to parse YAML workflow")?;
+async fn execute_workflow(
+    req: ExecuteRequest,
+    state: &ServerState,
+    run_id: &str,
+) -> anyhow::Result<(HashMap<String, Value>, Option<DebugMemory>)> {
+    // Read and parse workflow file
+    let file = req.file.clone();
+    let workflow = tokio::task::spawn_blocking(move || loader::load_workflow(std::path::Path::new(&file))).await??;
 
-    // Inject inputs
-    if let Some(inputs) = req.inputs {
-        for (k, v) in inputs {
-            workflow.global.insert(k, v);
-        }
+    // Inputs are validated against the declared `inputs:` by the builder
+    let mut builder = Engine::builder()
+        .workflow(workflow)
+        .inputs(req.inputs.unwrap_or_default())
+        .memory(state.backend.clone())
+        .executor_registry(state.executors.clone())
+        .run_id(run_id);
+    if let Some(session) = req.session_id {
+        builder = builder.session(session);
     }
+    let result = builder.build()?.run().await?;
+
+    // Return declared outputs, falling back to every node output
+    let outputs = result.results();
 
-    // Execute
-    let engine = Engine::new(workflow);
-    engine.execute().await?;
+    let debug = req.debug.then(|| DebugMemory {
+        global_memory: result.global_memory,
+        node_outputs: result.node_outputs,
+        journal: result.journal,
+    });
 
-    // Return outputs
-    Ok(engine.get_node_memory().get_all_values())
+    Ok((outputs, debug))
 }
@@ -0,0 +1,408 @@
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Key/value storage behind `GlobalMemory` and `NodeMemory`.
+///
+/// Every store is scoped to a single namespace (e.g. `<run_id>/global`),
+/// so several runs can share one backend without clobbering each other.
+pub trait MemoryStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Value>>;
+    fn set(&self, key: &str, value: Value) -> Result<()>;
+    fn remove(&self, key: &str) -> Result<Option<Value>>;
+    fn entries(&self) -> Result<Vec<(String, Value)>>;
+}
+
+/// Which backend to open stores from.
+///
+/// Parsed from the CLI `--memory` flag:
+/// - `memory` (default): in-process only, lost when the process exits
+/// - `json:<dir>`: one directory per namespace under `<dir>`, one JSON file per key
+/// - `sqlite:<file>`: a single embedded SQLite database
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MemoryBackend {
+    #[default]
+    InMemory,
+    Json(PathBuf),
+    Sqlite(PathBuf),
+}
+
+impl MemoryBackend {
+    pub fn open(&self, namespace: &str) -> Result<Arc<dyn MemoryStore>> {
+        match self {
+            MemoryBackend::InMemory => Ok(Arc::new(InMemoryStore::new())),
+            MemoryBackend::Json(dir) => Ok(Arc::new(JsonFileStore::open(dir, namespace)?)),
+            MemoryBackend::Sqlite(path) => Ok(Arc::new(SqliteStore::open(path, namespace)?)),
+        }
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        !matches!(self, MemoryBackend::InMemory)
+    }
+}
+
+impl FromStr for MemoryBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "memory" => Ok(MemoryBackend::InMemory),
+            Some(("json", path)) if !path.is_empty() => Ok(MemoryBackend::Json(PathBuf::from(path))),
+            Some(("sqlite", path)) if !path.is_empty() => Ok(MemoryBackend::Sqlite(PathBuf::from(path))),
+            _ => anyhow::bail!(
+                "Invalid memory backend '{}' (expected 'memory', 'json:<dir>' or 'sqlite:<file>')",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for MemoryBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryBackend::InMemory => write!(f, "memory"),
+            MemoryBackend::Json(dir) => write!(f, "json:{}", dir.display()),
+            MemoryBackend::Sqlite(path) => write!(f, "sqlite:{}", path.display()),
+        }
+    }
+}
+
+/// Process-local store (the original `DashMap` behaviour)
+#[derive(Default)]
+pub struct InMemoryStore {
+    data: DashMap<String, Value>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MemoryStore for InMemoryStore {
+    fn get(&self, key: &str) -> Result<Option<Value>> {
+        Ok(self.data.get(key).map(|v| v.clone()))
+    }
+
+    fn set(&self, key: &str, value: Value) -> Result<()> {
+        self.data.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<Option<Value>> {
+        Ok(self.data.remove(key).map(|(_, v)| v))
+    }
+
+    fn entries(&self) -> Result<Vec<(String, Value)>> {
+        Ok(self
+            .data
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect())
+    }
+}
+
+/// Keeps the namespace in memory and writes each key to its own JSON file,
+/// so a change only rewrites the file of the key that changed
+pub struct JsonFileStore {
+    dir: PathBuf,
+    data: Mutex<HashMap<String, Value>>,
+}
+
+/// On-disk form of one key; the key is stored inside since file names are encoded
+#[derive(Serialize, Deserialize)]
+struct JsonEntry {
+    key: String,
+    value: Value,
+}
+
+impl JsonFileStore {
+    pub fn open(dir: &Path, namespace: &str) -> Result<Self> {
+        let dir = dir.join(encode_name(namespace));
+
+        let mut data = HashMap::new();
+        if dir.exists() {
+            let files = std::fs::read_dir(&dir)
+                .with_context(|| format!("Failed to read memory directory {:?}", dir))?;
+            for file in files {
+                let path = file?.path();
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+                let content = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read memory file {:?}", path))?;
+                let entry: JsonEntry = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse memory file {:?}", path))?;
+                data.insert(entry.key, entry.value);
+            }
+        }
+
+        Ok(Self {
+            dir,
+            data: Mutex::new(data),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", encode_name(key)))
+    }
+
+    fn write(&self, key: &str, value: &Value) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create memory directory {:?}", self.dir))?;
+
+        // Write to a sibling file first so a crash never leaves a half-written value
+        let path = self.path(key);
+        let tmp = path.with_extension("json.tmp");
+        let entry = JsonEntry { key: key.to_string(), value: value.clone() };
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&entry)?)
+            .with_context(|| format!("Failed to write memory file {:?}", tmp))?;
+        std::fs::rename(&tmp, &path)
+            .with_context(|| format!("Failed to replace memory file {:?}", path))?;
+        Ok(())
+    }
+}
+
+impl MemoryStore for JsonFileStore {
+    fn get(&self, key: &str) -> Result<Option<Value>> {
+        Ok(self.data.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: Value) -> Result<()> {
+        let mut data = self.data.lock().unwrap();
+        self.write(key, &value)?;
+        data.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<Option<Value>> {
+        let mut data = self.data.lock().unwrap();
+        if !data.contains_key(key) {
+            return Ok(None);
+        }
+        let path = self.path(key);
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to delete memory file {:?}", path))?;
+        Ok(data.remove(key))
+    }
+
+    fn entries(&self) -> Result<Vec<(String, Value)>> {
+        Ok(self
+            .data
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+}
+
+/// Stores every namespace as rows of one `memory` table in a SQLite file
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+    namespace: String,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path, namespace: &str) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite memory store {:?}", path))?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS memory (
+                namespace  TEXT NOT NULL,
+                key        TEXT NOT NULL,
+                value      TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (namespace, key)
+            );",
+        )
+        .context("Failed to initialize SQLite memory schema")?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            namespace: namespace.to_string(),
+        })
+    }
+}
+
+impl MemoryStore for SqliteStore {
+    fn get(&self, key: &str) -> Result<Option<Value>> {
+        let conn = self.conn.lock().unwrap();
+        let raw: Option<String> = conn
+            .query_row(
+                "SELECT value FROM memory WHERE namespace = ?1 AND key = ?2",
+                params![self.namespace, key],
+                |row| row.get(0),
+            )
+            .optional()
+            .with_context(|| format!("Failed to read '{}/{}' from SQLite", self.namespace, key))?;
+
+        raw.map(|s| serde_json::from_str(&s))
+            .transpose()
+            .with_context(|| format!("Invalid JSON stored at '{}/{}'", self.namespace, key))
+    }
+
+    fn set(&self, key: &str, value: Value) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO memory (namespace, key, value, updated_at)
+             VALUES (?1, ?2, ?3, strftime('%s', 'now'))
+             ON CONFLICT (namespace, key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+            params![self.namespace, key, serde_json::to_string(&value)?],
+        )
+        .with_context(|| format!("Failed to write '{}/{}' to SQLite", self.namespace, key))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<Option<Value>> {
+        let old = self.get(key)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM memory WHERE namespace = ?1 AND key = ?2",
+            params![self.namespace, key],
+        )
+        .with_context(|| format!("Failed to delete '{}/{}' from SQLite", self.namespace, key))?;
+        Ok(old)
+    }
+
+    fn entries(&self) -> Result<Vec<(String, Value)>> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn
+            .prepare("SELECT key, value FROM memory WHERE namespace = ?1 ORDER BY key")
+            .and_then(|mut stmt| {
+                stmt.query_map(params![self.namespace], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .with_context(|| format!("Failed to scan '{}' in SQLite", self.namespace))?;
+
+        rows.into_iter()
+            .map(|(k, v)| {
+                let value = serde_json::from_str(&v)
+                    .with_context(|| format!("Invalid JSON stored at '{}/{}'", self.namespace, k))?;
+                Ok((k, value))
+            })
+            .collect()
+    }
+}
+
+/// Turn a namespace like `run-1/global` or a key into a file name.
+///
+/// Bytes other than ASCII letters, digits, `-` and `_` are written as `%XX`,
+/// so distinct names never share a file.
+fn encode_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("workflow-engine-{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    /// Set, overwrite, read back, list and remove through one store
+    fn round_trip(store: &dyn MemoryStore) {
+        store.set("a", json!(1)).unwrap();
+        store.set("b/c", json!({"x": [1, 2]})).unwrap();
+        store.set("a", json!("two")).unwrap();
+
+        assert_eq!(store.get("a").unwrap(), Some(json!("two")));
+        assert_eq!(store.get("missing").unwrap(), None);
+        let mut entries = store.entries().unwrap();
+        entries.sort_by(|x, y| x.0.cmp(&y.0));
+        assert_eq!(entries, vec![("a".to_string(), json!("two")), ("b/c".to_string(), json!({"x": [1, 2]}))]);
+
+        assert_eq!(store.remove("a").unwrap(), Some(json!("two")));
+        assert_eq!(store.remove("a").unwrap(), None);
+        assert_eq!(store.get("a").unwrap(), None);
+    }
+
+    #[test]
+    fn in_memory_round_trip() {
+        round_trip(&InMemoryStore::new());
+    }
+
+    #[test]
+    fn json_round_trip_and_reopen() {
+        let dir = temp_path("json");
+        round_trip(&JsonFileStore::open(&dir, "run/global").unwrap());
+
+        let reopened = JsonFileStore::open(&dir, "run/global").unwrap();
+        assert_eq!(reopened.get("b/c").unwrap(), Some(json!({"x": [1, 2]})));
+        assert_eq!(reopened.get("a").unwrap(), None);
+        assert!(JsonFileStore::open(&dir, "run/nodes").unwrap().entries().unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_keys_differing_only_in_punctuation_keep_their_own_file() {
+        let dir = temp_path("json-keys");
+        let store = JsonFileStore::open(&dir, "ns").unwrap();
+        store.set("a/b", json!(1)).unwrap();
+        store.set("a_b", json!(2)).unwrap();
+        store.set("a.b", json!(3)).unwrap();
+
+        let reopened = JsonFileStore::open(&dir, "ns").unwrap();
+        assert_eq!(reopened.get("a/b").unwrap(), Some(json!(1)));
+        assert_eq!(reopened.get("a_b").unwrap(), Some(json!(2)));
+        assert_eq!(reopened.get("a.b").unwrap(), Some(json!(3)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_round_trip_and_namespaces() {
+        let dir = temp_path("sqlite");
+        let path = dir.join("memory.db");
+        round_trip(&SqliteStore::open(&path, "run/global").unwrap());
+
+        let other = SqliteStore::open(&path, "run/nodes").unwrap();
+        other.set("b/c", json!("other")).unwrap();
+        let reopened = SqliteStore::open(&path, "run/global").unwrap();
+        assert_eq!(reopened.get("b/c").unwrap(), Some(json!({"x": [1, 2]})));
+        assert_eq!(other.get("b/c").unwrap(), Some(json!("other")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn encode_name_is_injective() {
+        assert_eq!(encode_name("run-1_x"), "run-1_x");
+        assert_eq!(encode_name("a/b"), "a%2Fb");
+        assert_ne!(encode_name("a/b"), encode_name("a_b"));
+        assert_ne!(encode_name("a%2Fb"), encode_name("a/b"));
+        assert_eq!(encode_name("é"), "%C3%A9");
+    }
+
+    #[test]
+    fn parse_backend() {
+        assert_eq!("memory".parse::<MemoryBackend>().unwrap(), MemoryBackend::InMemory);
+        assert_eq!("json:dir".parse::<MemoryBackend>().unwrap(), MemoryBackend::Json(PathBuf::from("dir")));
+        assert_eq!("sqlite:a.db".parse::<MemoryBackend>().unwrap(), MemoryBackend::Sqlite(PathBuf::from("a.db")));
+        assert!("json:".parse::<MemoryBackend>().is_err());
+        assert!("redis:x".parse::<MemoryBackend>().is_err());
+    }
+}
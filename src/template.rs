@@ -13,7 +13,7 @@ impl TemplateEngine {
         Self { global, nodes }
     }
 
-    /// Replace variables in format {{ global.key }} or {{ nodes.id.output }}
+    /// Replace variables in format {{ global.key }}, {{ conversation.key }} or {{ nodes.id.output }}
     pub fn render(&self, template: &str) -> Result<String> {
         let re = Regex::new(r"\{\{\s*([^}]+)\s*\}\}").unwrap();
         let mut result = template.to_string();
@@ -31,6 +31,32 @@ impl TemplateEngine {
         Ok(result)
     }
 
+    /// Like `render`, but a template that is exactly one `{{ expr }}` keeps
+    /// the JSON type of the resolved value instead of being stringified
+    pub fn evaluate(&self, template: &str) -> Result<Value> {
+        let re = Regex::new(r"^\s*\{\{\s*([^}]+?)\s*\}\}\s*$").unwrap();
+        if let Some(cap) = re.captures(template) {
+            return self.resolve_expression(cap[1].trim());
+        }
+        Ok(Value::String(self.render(template)?))
+    }
+
+    /// Evaluate every string inside `value`, recursing into arrays and objects
+    pub fn render_value(&self, value: &Value) -> Result<Value> {
+        match value {
+            Value::String(s) => self.evaluate(s),
+            Value::Array(arr) => arr.iter().map(|v| self.render_value(v)).collect::<Result<Vec<_>>>().map(Value::Array),
+            Value::Object(obj) => {
+                let mut rendered = serde_json::Map::new();
+                for (k, v) in obj {
+                    rendered.insert(k.clone(), self.render_value(v)?);
+                }
+                Ok(Value::Object(rendered))
+            }
+            _ => Ok(value.clone()),
+        }
+    }
+
     /// Resolve an expression like "global.api_url" or "nodes.fetch_data.output.stdout"
     fn resolve_expression(&self, expr: &str) -> Result<Value> {
         // Split by dot, but we need to handle array indexing like users[0]
@@ -45,7 +71,7 @@ impl TemplateEngine {
         
         let parts_refs: Vec<&str> = parts.iter().map(|s| s.as_str()).collect();
 
-        match parts_refs.get(0) {
+        match parts_refs.first() {
             Some(&"global") => {
                 if parts_refs.len() < 2 {
                     anyhow::bail!("Invalid global reference: {}", expr);
@@ -53,7 +79,7 @@ impl TemplateEngine {
                 let key = parts_refs[1];
                 let value = self
                     .global
-                    .get(key)
+                    .get(key)?
                     .with_context(|| format!("Global variable '{}' not found", key))?;
                 
                 self.traverse_path(&value, &parts_refs[2..])
@@ -69,7 +95,7 @@ impl TemplateEngine {
                     "output" => {
                         let output = self
                             .nodes
-                            .get_output_value(node_id)
+                            .get_output_value(node_id)?
                             .with_context(|| format!("Node '{}' output not found", node_id))?;
                         
                         self.traverse_path(&output, &parts_refs[3..])
@@ -77,8 +103,20 @@ impl TemplateEngine {
                     _ => anyhow::bail!("Unknown node field: {}", field),
                 }
             }
+            Some(&"conversation") => {
+                if parts_refs.len() < 2 {
+                    anyhow::bail!("Invalid conversation reference: {}", expr);
+                }
+                let key = parts_refs[1];
+                let value = self
+                    .global
+                    .get_conversation(key)?
+                    .with_context(|| format!("Conversation variable '{}' not found", key))?;
+
+                self.traverse_path(&value, &parts_refs[2..])
+            }
             Some(&"loop") => {
-                let loop_ctx = self.global.get("loop")
+                let loop_ctx = self.global.get("loop")?
                     .context("Loop context not found (are you inside a loop node?)")?;
                 
                 if parts_refs.len() < 2 {
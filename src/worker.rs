@@ -7,23 +7,43 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
 use anyhow::Result;
 use serde_json::Value;
 
+use crate::http_pool::{HttpConfig, HttpPool};
 use crate::schema::Node;
 use crate::memory::{GlobalMemory, NodeMemory, NodeOutput};
-use crate::nodes::get_executor;
+use crate::nodes::ExecutorRegistry;
 
 #[derive(Clone)]
 struct WorkerState {
     id: String,
+    executors: ExecutorRegistry,
+    /// One pool per job, so its host limits hold across the nodes sent here
+    pools: Arc<DashMap<String, JobPool>>,
 }
 
+struct JobPool {
+    pool: HttpPool,
+    last_used: Instant,
+}
+
+/// Pools of jobs that sent nothing for this long are dropped
+const POOL_IDLE: Duration = Duration::from_secs(600);
+
 #[derive(Deserialize, Serialize)]
 pub struct ExecuteRequest {
     pub node: Node,
     pub global_memory: HashMap<String, Value>,
     pub node_outputs: HashMap<String, NodeOutput>,
+    /// Job the node belongs to; nodes of one job share its HTTP limits
+    #[serde(default)]
+    pub job_id: Option<String>,
+    /// The workflow's per-host HTTP limits
+    #[serde(default)]
+    pub http: HttpConfig,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -31,6 +51,12 @@ pub struct ExecuteResponse {
     pub status: String,
     pub output: Option<NodeOutput>,
     pub error: Option<String>,
+    /// Globals the node set, for the coordinator to apply to the job
+    #[serde(default)]
+    pub global_updates: HashMap<String, Value>,
+    /// Globals the node removed
+    #[serde(default)]
+    pub global_removed: Vec<String>,
 }
 
 #[derive(Serialize)]
@@ -39,9 +65,11 @@ pub struct HealthResponse {
     pub worker_id: String,
 }
 
-pub async fn run_worker(worker_id: String, port: u16) -> Result<()> {
+pub async fn run_worker(worker_id: String, port: u16, executors: ExecutorRegistry) -> Result<()> {
     let state = WorkerState {
         id: worker_id.clone(),
+        executors,
+        pools: Arc::new(DashMap::new()),
     };
 
     let app = Router::new()
@@ -65,24 +93,47 @@ async fn handle_execute(
     log::info!("[Worker {}] Executing node: {}", state.id, req.node.id);
 
     // Reconstruct memory from request
-    let global = GlobalMemory::new();
-    for (k, v) in req.global_memory {
-        global.set(k, v);
-    }
+    let pool = match &req.job_id {
+        Some(job_id) => {
+            state.pools.retain(|_, job| job.last_used.elapsed() < POOL_IDLE);
+            let mut job = state.pools.entry(job_id.clone()).or_insert_with(|| JobPool {
+                pool: HttpPool::new(&req.http),
+                last_used: Instant::now(),
+            });
+            job.last_used = Instant::now();
+            job.pool.clone()
+        }
+        None => HttpPool::new(&req.http),
+    };
+    let sent = req.global_memory;
+    let result = async {
+        let global = GlobalMemory::new().with_http(pool);
+        for (k, v) in &sent {
+            global.set(k.clone(), v.clone())?;
+        }
+
+        let nodes = NodeMemory::new();
+        for (k, v) in req.node_outputs {
+            nodes.set(k, v)?;
+        }
 
-    let nodes = NodeMemory::new();
-    for (k, v) in req.node_outputs {
-        nodes.set(k, v);
+        // Execute the node
+        let output = execute_node(&state.executors, &req.node, &global, &nodes).await?;
+
+        let (updates, removed) = global_changes(&sent, global.get_all()?.into_iter().collect());
+        anyhow::Ok((output, updates, removed))
     }
+    .await;
 
-    // Execute the node
-    match execute_node(&req.node, &global, &nodes).await {
-        Ok(output) => {
+    match result {
+        Ok((output, global_updates, global_removed)) => {
             log::info!("[Worker {}] Node {} completed successfully", state.id, req.node.id);
             Json(ExecuteResponse {
                 status: "success".to_string(),
                 output: Some(output),
                 error: None,
+                global_updates,
+                global_removed,
             })
         }
         Err(e) => {
@@ -91,11 +142,21 @@ async fn handle_execute(
                 status: "failed".to_string(),
                 output: None,
                 error: Some(e.to_string()),
+                global_updates: HashMap::new(),
+                global_removed: Vec::new(),
             })
         }
     }
 }
 
+/// Globals set and removed since `sent`; only these are reported, so
+/// parallel nodes don't undo each other's writes
+fn global_changes(sent: &HashMap<String, Value>, after: HashMap<String, Value>) -> (HashMap<String, Value>, Vec<String>) {
+    let removed = sent.keys().filter(|k| !after.contains_key(*k)).cloned().collect();
+    let updates = after.into_iter().filter(|(k, v)| sent.get(k) != Some(v)).collect();
+    (updates, removed)
+}
+
 async fn handle_health(State(state): State<WorkerState>) -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "healthy".to_string(),
@@ -104,10 +165,27 @@ async fn handle_health(State(state): State<WorkerState>) -> Json<HealthResponse>
 }
 
 async fn execute_node(
+    executors: &ExecutorRegistry,
     node: &Node,
     global: &GlobalMemory,
     nodes: &NodeMemory,
 ) -> Result<NodeOutput> {
-    let executor = get_executor(&node.node_type)?;
+    let executor = executors.get(&node.node_type)?;
     executor.execute(node, global, nodes).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn only_changed_globals_are_reported() {
+        let sent: HashMap<String, Value> = serde_json::from_value(json!({"same": 1, "changed": 1, "gone": 1})).unwrap();
+        let after = serde_json::from_value(json!({"same": 1, "changed": 2, "new": [1]})).unwrap();
+
+        let (updates, removed) = global_changes(&sent, after);
+        assert_eq!(updates, serde_json::from_value::<HashMap<String, Value>>(json!({"changed": 2, "new": [1]})).unwrap());
+        assert_eq!(removed, ["gone"]);
+    }
+}
@@ -0,0 +1,46 @@
+#!/usr/bin/env python3
+"""Example node-type plugin: one JSON request per line on stdin, one reply per line on stdout."""
+import json
+import sys
+
+DESCRIPTION = {
+    "types": [
+        {
+            "type": "crm_lookup",
+            "description": "Look up a customer record",
+            "params": {
+                "type": "object",
+                "properties": {
+                    "email": {"type": "string"},
+                    "fields": {"type": "array", "items": {"type": "string"}},
+                },
+                "required": ["email"],
+                "additionalProperties": False,
+            },
+        }
+    ]
+}
+
+CUSTOMERS = {
+    "ada@example.com": {"name": "Ada Lovelace", "plan": "enterprise", "seats": 40},
+}
+
+
+def execute(node):
+    params = node["params"]
+    record = CUSTOMERS.get(params["email"])
+    if record is None:
+        return {"error": "no customer with email " + params["email"]}
+    fields = params.get("fields") or list(record)
+    return {"status": "success", "output": {k: record[k] for k in fields if k in record}}
+
+
+for line in sys.stdin:
+    request = json.loads(line)
+    if request["method"] == "describe":
+        reply = DESCRIPTION
+    elif request["method"] == "execute":
+        reply = execute(request["node"])
+    else:
+        reply = {"error": "unknown method " + request["method"]}
+    print(json.dumps(reply), flush=True)